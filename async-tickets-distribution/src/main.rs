@@ -2,6 +2,7 @@
 
 use udp_ticket_distribution::*;
 use std::io::{stdin, stdout, Write};
+use std::time::Duration;
 use menu::*;
 
 #[doc(hidden)]
@@ -16,6 +17,7 @@ async fn main() {
         match menu.start().as_str(){
             "client_mode" => client_mode().await,
             "server_mode" => server_mode().await,
+            "gate_mode" => gate_mode().await,
             "exit" => return,
             _ => (),
         }// async Fn traits not implemented as needed, using this workaround
@@ -33,6 +35,7 @@ fn build_menu() -> CLMenu{
     );
     menu_main_page.add(CLPageOption::new_with_closure("Passenger", "Main", "client_mode"));
     menu_main_page.add(CLPageOption::new_with_closure("Distributor", "Main", "server_mode"));
+    menu_main_page.add(CLPageOption::new_with_closure("Gate agent", "Main", "gate_mode"));
     menu_main_page.add(CLPageOption::new_with_closure("Exit", "", "exit"));
     menu.add_menu(menu_main_page);
 
@@ -64,8 +67,10 @@ async fn client_mode(){
     // (loop)
     //input op(["fetch", "flights"], ["ticketfor", "flight_number"], ["exit"])
     //send recv print
+    let mut holds: Vec<TicketHold> = Vec::new();
+    print_help(CLIENT_COMMANDS);
     loop {
-        print!("What do you want to do? (fetch flights | ticketfor [flight_number] | exit) \n input: ");
+        print!("What do you want to do? (help | exit) \n input: ");
         stdout().flush().unwrap();
         let mut input = String::new();
         stdin().read_line(&mut input).unwrap();
@@ -74,13 +79,18 @@ async fn client_mode(){
             ["fetch","flights"] => {
                 let data = psngr.fetch_flights().await;
                 if let Err(e) = data {
-                    eprintln!("Error: {}", e.to_string());
+                    eprintln!("Error: {}", e);
                     //sleep?
                     continue;
                 }
                 println!("Recieved data:");
+                let now = std::time::SystemTime::now();
                 for flight in data.unwrap(){
-                    println!("Flight: {:3} | seats: {}",flight.num, flight.seats_num);
+                    match flight.opens_in(now){
+                        Some(left) => println!("Flight: {:3} | seats: {} | sales open in {} s",flight.num, flight.seats_num, left.as_secs()),
+                        None if !flight.is_on_sale(now) => println!("Flight: {:3} | seats: {} | sales closed",flight.num, flight.seats_num),
+                        None => println!("Flight: {:3} | seats: {}",flight.num, flight.seats_num),
+                    }
                 }
                 println!("End of recieved data.");
             },
@@ -95,7 +105,7 @@ async fn client_mode(){
 
                 let data = psngr.query_ticket_for_a_flight(flight_number).await;
                 if let Err(e) = data {
-                    eprintln!("Error: {}", e.to_string());
+                    eprintln!("Error: {}", e);
                     //sleep?
                     continue;
                 }
@@ -105,6 +115,197 @@ async fn client_mode(){
                     None => println!("Ticket did not received. Check if flight is still available."),
                 }
             },
+            ["ticketin", flight_number, class] => {
+                let (Ok(flight_number), Some(class)) = (flight_number.parse::<u32>(), parse_class(class)) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: ticketin 1 business)");
+                    continue;
+                };
+
+                match psngr.query_ticket_in_class(flight_number, class).await{
+                    Ok(Some(t)) => println!("Recieved ticket {} for a flight {}, price: {}",t.ticket,flight_number,price_str(t.fare.price)),
+                    Ok(None) => println!("Ticket did not received. Check if the class is still available."),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["tickets"] => {
+                println!("Acquired tickets:");
+                for t in psngr.acquired_tickets(){
+                    println!("Flight: {:3} | seat: {:3} | booking: {} | price: {} | signed: {}",
+                        t.flight_num, t.ticket, t.booking_ref, price_str(t.fare.price), t.signed.is_some());
+                }
+                println!("Acquired tickets end.");
+            },
+            ["ticketpromo", flight_number, code] => {
+                let Ok(flight_number) = flight_number.parse::<u32>() else {
+                    println!("Invalid input. Maybe a typoo? (Ex: ticketpromo 1 SPRING)");
+                    continue;
+                };
+
+                match psngr.query_ticket_with_promo(flight_number, None, code).await{
+                    Ok(Some(t)) => println!("Recieved ticket {} for a flight {}, price: {}",t.ticket,flight_number,price_str(t.fare.price)),
+                    Ok(None) => println!("Ticket did not received. Check if flight is still available."),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["hold", flight_number, class] => {
+                let class = if class == "any" { Some(None) } else { parse_class(class).map(Some) };
+
+                let (Ok(flight_number), Some(class)) = (flight_number.parse::<u32>(), class) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: hold 1 any)");
+                    continue;
+                };
+
+                match psngr.hold_ticket(flight_number, class).await{
+                    Ok(Some(hold)) => {
+                        let left = hold.expires.duration_since(std::time::SystemTime::now()).unwrap_or_default();
+                        println!("Hold {}: seat {} for {}, buy it in {} s",holds.len(),hold.seat,price_str(hold.fare.price),left.as_secs());
+                        holds.push(hold);
+                    },
+                    Ok(None) => println!("Ticket is not held. Check if flight is still available."),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["buy", hold_number] => {
+                let Some(hold) = hold_number.parse::<usize>().ok().and_then(|i| holds.get(i)) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: buy 0)");
+                    continue;
+                };
+
+                match psngr.buy_held(hold).await{
+                    Ok(t) => println!("Recieved ticket {} for a flight {}, price: {}",t.ticket,t.flight_num,price_str(t.fare.price)),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["enter", flight_number] => {
+                let Ok(flight_number) = flight_number.parse::<u32>() else {
+                    println!("Invalid input. Maybe a typoo? (Ex: enter 1)");
+                    continue;
+                };
+
+                match psngr.enter_lottery(flight_number).await{
+                    Ok(()) => println!("You are in the lottery for a flight {}",flight_number),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["queue", flight_number] => {
+                let Ok(flight_number) = flight_number.parse::<u32>() else {
+                    println!("Invalid input. Maybe a typoo? (Ex: queue 1)");
+                    continue;
+                };
+
+                let mut place = psngr.join_queue(flight_number).await;
+                while let Ok(p) = &place {
+                    if p.is_admitted() { break; }
+
+                    println!("Position in the queue: {}",p.position);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    place = psngr.queue_position(flight_number).await;
+                }
+
+                match place{
+                    Ok(_) => println!("You are admitted! Now you can buy a ticket for a flight {}",flight_number),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["id", passenger_id] => {
+                psngr.set_id(passenger_id);
+                println!("You are passenger {} now!", passenger_id);
+            },
+            ["transfer", ticket_number, passenger_id] => {
+                let Some(ticket) = ticket_number.parse::<usize>().ok().and_then(|i| psngr.acquired_tickets().get(i).cloned()) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: transfer 0 bob)");
+                    continue;
+                };
+
+                match psngr.transfer_ticket(&ticket, passenger_id).await{
+                    Ok(given) => println!("Ticket {} for a flight {} is given to {}. They claim it with: claim {} {} {:016x}",
+                        ticket.ticket,ticket.flight_num,passenger_id,given.flight_num,given.booking_ref,given.secret.unwrap_or_default()),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["claim", flight_number, booking_ref, secret] => {
+                let (Ok(flight_number), Ok(secret)) = (flight_number.parse::<u32>(), u64::from_str_radix(secret, 16)) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: claim 1 ABC234 00ff00ff00ff00ff)");
+                    continue;
+                };
+
+                match psngr.claim_ticket(flight_number, booking_ref, secret).await{
+                    Ok(t) => println!("Ticket {} for a flight {} is yours now!",t.ticket,t.flight_num),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["seat", ticket_number, seat] => {
+                let Some(ticket) = ticket_number.parse::<usize>().ok().and_then(|i| psngr.acquired_tickets().get(i).cloned()) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: seat 0 A1)");
+                    continue;
+                };
+
+                match psngr.change_seat(&ticket, seat).await{
+                    Ok(t) => println!("Seat changed from {} to {} on a flight {}. Check in again if you were checked in.",ticket.ticket,t.ticket,t.flight_num),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["trip", ref flights @ ..] if flights.len() >= 2 => {
+                let Ok(legs) = flights.iter().map(|f| f.parse::<u32>().map(ItineraryLeg::new)).collect::<Result<Vec<_>, _>>() else {
+                    println!("Invalid input. Maybe a typoo? (Ex: trip 1 2)");
+                    continue;
+                };
+
+                match psngr.book_itinerary(&legs).await{
+                    Ok(Some(trip)) => {
+                        println!("Trip booked! Booking: {}", trip.booking_ref);
+                        for t in &trip.legs{
+                            println!("Flight: {:3} | seat: {:3} | price: {}", t.flight_num, t.ticket, price_str(t.fare.price));
+                        }
+                    },
+                    Ok(None) => println!("Trip is not booked. Check if every flight is still available."),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["cancel", ticket_number] => {
+                let Some(ticket) = ticket_number.parse::<usize>().ok().and_then(|i| psngr.acquired_tickets().get(i).cloned()) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: cancel 0)");
+                    continue;
+                };
+
+                match psngr.cancel_ticket(&ticket).await{
+                    Ok(()) => println!("Ticket {} for a flight {} is cancelled",ticket.ticket,ticket.flight_num),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["sync", "tickets"] => {
+                match psngr.fetch_tickets().await{
+                    Ok(tickets) => println!("You have {} tickets now!", tickets.len()),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["checkin", ticket_number] => {
+                let Some(ticket) = ticket_number.parse::<usize>().ok().and_then(|i| psngr.acquired_tickets().get(i).cloned()) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: checkin 0)");
+                    continue;
+                };
+
+                match psngr.check_in(&ticket).await{
+                    Ok(pass) => {
+                        println!("Checked in! Flight: {} | seat: {} | group: {} | sequence: {}",pass.flight_num,pass.seat,pass.check_in.group,pass.check_in.sequence);
+                        println!("Boarding pass code: {}",hex_str(&pass.to_bytes()));
+                    },
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["help"] => print_help(CLIENT_COMMANDS),
+            ["lottery", flight_number] => {
+                let Ok(flight_number) = flight_number.parse::<u32>() else {
+                    println!("Invalid input. Maybe a typoo? (Ex: lottery 1)");
+                    continue;
+                };
+
+                match psngr.lottery_status(flight_number).await{
+                    Ok(LotteryStatus::Won{ seat, fare, .. }) => println!("You won ticket {} ({:?}, {}) for a flight {}",seat,fare.class,price_str(fare.price),flight_number),
+                    Ok(status) => println!("Lottery status: {:?}",status),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
             ["exit"] => break,
             _ => println!("Invalid input. Maybe a typoo?"),
         }
@@ -117,7 +318,7 @@ async fn server_mode(){
     //welcome
     println!("You are a Distributor!");
     
-    let distr;
+    let mut distr;
     //input ip:port of the server
     print!("Please, input your desired ip:port numbers (Ex: 127.0.0.1:8080) or simple type \"exit\"\nThis input is everything sensitive\n input: ");
     stdout().flush().unwrap();
@@ -138,17 +339,19 @@ async fn server_mode(){
     // (loop)
     //start + logging|stop|exit
     println!("Server started!");
+    print_help(SERVER_COMMANDS);
     loop {
-        print!("What do you want to do? ( see flights | genflight [1..42] | exit) \n input: ");
+        print!("What do you want to do? (help | exit) \n input: ");
         stdout().flush().unwrap();
         let mut input = String::new();
         stdin().read_line(&mut input).unwrap();
         let input: Vec<&str> = input.split_whitespace().collect();
         match input[..]{
+            ["help"] => print_help(SERVER_COMMANDS),
             ["see", "flights"] => {
                 println!("Flights info:");
-                for f in distr.db_storage().read().unwrap().iter(){
-                    println!("Flight: {:3}, seats: {:3}",f.info.num, f.info.seats_num)
+                for f in distr.db_storage().snapshot(){
+                    println!("Flight: {:3}, seats: {:3}, revenue: {}, checked in: {}",f.info.num, f.info.seats_num, price_str(f.revenue()), f.checked_in_count())
                 }
                 println!("Flights info end.");
             },
@@ -161,12 +364,418 @@ async fn server_mode(){
                     }
                 };
 
-                distr.gen_fake_flight(seats_rows).await;
+                match distr.gen_fake_flight(seats_rows).await{
+                    Some(_) => println!("Flight created! Now you can find him with command \"see flights\"."),
+                    None => println!("Flight is not created. No free flight number is routed to this distributor."),
+                }
+            },
+            ["quota", per_flight, overall] => {
+                let parse = |v: &str| if v == "-" { Ok(None) } else { v.parse::<u32>().map(Some) };
+
+                let (Ok(per_flight), Ok(overall)) = (parse(per_flight), parse(overall)) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: quota 2 -)");
+                    continue;
+                };
+
+                distr.set_quota(QuotaRules{ per_flight, overall });
 
-                println!("Flight created! Now you can find him with command \"see flights\".");
+                println!("Quota updated! Per flight: {:?}, overall: {:?}", per_flight, overall);
+            },
+            ["lottery", flight_number, secs] => {
+                let (Ok(flight_number), Ok(secs)) = (flight_number.parse::<u32>(), secs.parse::<u64>()) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: lottery 1 60)");
+                    continue;
+                };
+
+                match distr.start_lottery(flight_number, Duration::from_secs(secs)){
+                    Ok(()) => println!("Lottery started! Entries are open for {} seconds.", secs),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["fare", flight_number, seats, class, price] => {
+                let range = match seats.split_once('-'){
+                    Some((from, to)) => match (from.parse::<u8>(), to.parse::<u8>()){
+                        (Ok(from), Ok(to)) => Some(SeatRange::Rows(from..=to)),
+                        _ => None,
+                    },
+                    None => match seats.parse::<u8>(){
+                        Ok(row) => Some(SeatRange::Rows(row..=row)),
+                        Err(_) => Some(SeatRange::Seat(seats.to_uppercase())),
+                    },
+                };
+
+                let (Ok(flight_number), Some(range), Some(class), Ok(price)) = 
+                    (flight_number.parse::<u32>(), range, parse_class(class), price.parse::<u64>()) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: fare 1 1-3 business 25000)");
+                    continue;
+                };
+
+                match distr.set_fare(flight_number, range, Fare{ class, price }){
+                    Ok(()) => println!("Fare is set! {:?} for {}", class, price_str(price)),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["promo", code, discount, max_uses] => {
+                let discount = match discount.strip_suffix('%'){
+                    Some(percent) => percent.parse::<u8>().map(Discount::Percent),
+                    None => discount.parse::<u64>().map(Discount::Fixed),
+                };
+                let max_uses = if max_uses == "-" { Ok(None) } else { max_uses.parse::<u32>().map(Some) };
+
+                let (Ok(discount), Ok(max_uses)) = (discount, max_uses) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: promo SPRING 20% 100)");
+                    continue;
+                };
+
+                distr.add_promo(code, PromoCode{ max_uses, ..PromoCode::new(discount) });
+                println!("Promo code {} added!", code);
+            },
+            ["pubkey"] => {
+                println!("Tickets are signed with Ed25519 public key: {}", hex_str(&distr.public_key()));
+            },
+            ["boarding", flight_number] => {
+                let Ok(flight_number) = flight_number.parse::<u32>() else {
+                    println!("Invalid input. Maybe a typoo? (Ex: boarding 1)");
+                    continue;
+                };
+
+                match distr.boarding_count(flight_number){
+                    Ok(count) => println!("Flight {}: boarded {} of {} checked in", flight_number, count.boarded, count.checked_in),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["manifest", flight_number, format, path] => {
+                let format = match format{
+                    "csv" => Some(ManifestFormat::Csv),
+                    "json" => Some(ManifestFormat::Json),
+                    _ => None,
+                };
+
+                let (Ok(flight_number), Some(format)) = (flight_number.parse::<u32>(), format) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: manifest 1 csv manifest.csv)");
+                    continue;
+                };
+
+                match distr.export_manifest(flight_number, format){
+                    Ok(manifest) if path == "-" => print!("{}", manifest),
+                    Ok(manifest) => match std::fs::write(path, manifest){
+                        Ok(()) => println!("Manifest of a flight {} is saved to {}", flight_number, path),
+                        Err(e) => eprintln!("Error: {}", e),
+                    },
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["overbook", flight_number, percent] => {
+                let (Ok(flight_number), Ok(percent)) = (flight_number.parse::<u32>(), percent.parse::<u8>()) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: overbook 1 5)");
+                    continue;
+                };
+
+                match distr.set_overbooking(flight_number, percent){
+                    Ok(allowed) => println!("Flight {} may sell {} standby tickets", flight_number, allowed),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["resolve", flight_number] => {
+                let Ok(flight_number) = flight_number.parse::<u32>() else {
+                    println!("Invalid input. Maybe a typoo? (Ex: resolve 1)");
+                    continue;
+                };
+
+                match distr.resolve_overbooking(flight_number){
+                    Ok(resolution) => {
+                        for t in &resolution.reassigned{
+                            println!("Seated: {} | booking: {}", t.seat, t.booking_ref);
+                        }
+                        for t in &resolution.bumped{
+                            println!("Denied boarding: booking {} | passenger: {} | refund: {}", t.booking_ref, t.owner.passenger_id.as_deref().unwrap_or("-"), price_str(t.fare.price));
+                        }
+                        println!("Overbooking of a flight {} is resolved!", flight_number);
+                    },
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["gate", "allow", ip] => {
+                let Ok(ip) = ip.parse::<std::net::IpAddr>() else {
+                    println!("Invalid input. Maybe a typoo? (Ex: gate allow 127.0.0.1)");
+                    continue;
+                };
+
+                distr.allow_gate(ip);
+                println!("Gate {} is allowed!", ip);
+            },
+            ["gate", "revoke", ip] => {
+                let Ok(ip) = ip.parse::<std::net::IpAddr>() else {
+                    println!("Invalid input. Maybe a typoo? (Ex: gate revoke 127.0.0.1)");
+                    continue;
+                };
+
+                if distr.revoke_gate(ip) {
+                    println!("Gate {} is revoked!", ip);
+                } else {
+                    println!("Gate {} was not allowed.", ip);
+                }
+            },
+            ["close", flight_number] => {
+                let Ok(flight_number) = flight_number.parse::<u32>() else {
+                    println!("Invalid input. Maybe a typoo? (Ex: close 1)");
+                    continue;
+                };
+
+                match distr.close_flight(flight_number){
+                    Ok(manifest) => {
+                        println!("Flight {} is closed! Final manifest:", flight_number);
+                        for p in &manifest.passengers{
+                            println!("Seat: {:3} | booking: {} | passenger: {} | {:?}", p.seat, p.booking_ref, p.passenger_id.as_deref().unwrap_or("-"), p.status);
+                        }
+                        println!("Manifest end.");
+                    },
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["promos"] => {
+                println!("Promo codes usage:");
+                for (code, uses) in distr.promo_usages(){
+                    println!("Code: {:10} | used: {}", code, uses);
+                }
+                println!("Promo codes end.");
+            },
+            ["pricing", "off"] => {
+                distr.clear_pricing();
+                println!("Seats are sold at their fares!");
+            },
+            ["pricing", max_markup, velocity_markup] => {
+                let (Ok(max_markup), Ok(velocity_markup)) = (max_markup.parse::<f64>(), velocity_markup.parse::<f64>()) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: pricing 2.0 0.05)");
+                    continue;
+                };
+
+                distr.set_pricing(InventoryPricing{ max_markup, velocity_markup });
+                println!("Pricing updated! Last seat costs {} fares, every sale of the last hour adds {}.", max_markup, velocity_markup);
+            },
+            ["window", flight_number, opens_in, closes_in] => {
+                let now = std::time::SystemTime::now();
+                let parse = |v: &str| if v == "-" { Ok(None) } else { v.parse::<u64>().map(|s| Some(now + Duration::from_secs(s))) };
+
+                let (Ok(flight_number), Ok(opens), Ok(closes)) = (flight_number.parse::<u32>(), parse(opens_in), parse(closes_in)) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: window 1 60 -)");
+                    continue;
+                };
+
+                match distr.set_sale_window(flight_number, opens, closes){
+                    Ok(()) => println!("Sales window of a flight {} is set!", flight_number),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["departure", flight_number, departs_in] => {
+                let departure = if departs_in == "-" { Ok(None) } else { departs_in.parse::<u64>().map(|s| Some(std::time::SystemTime::now() + Duration::from_secs(s))) };
+
+                let (Ok(flight_number), Ok(departure)) = (flight_number.parse::<u32>(), departure) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: departure 1 7200)");
+                    continue;
+                };
+
+                match distr.set_departure(flight_number, departure){
+                    Ok(()) => println!("Departure of a flight {} is set!", flight_number),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["waitroom", flight_number, burst, per_second] => {
+                let (Ok(flight_number), Ok(burst), Ok(per_second)) = 
+                    (flight_number.parse::<u32>(), burst.parse::<u32>(), per_second.parse::<f64>()) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: waitroom 1 10 2.5)");
+                    continue;
+                };
+
+                let rate = RateLimit{ burst, per_second };
+
+                // Running waiting room only gets the new rate
+                let result = distr.open_waiting_room(flight_number, rate)
+                    .or_else(|_| distr.set_admission_rate(flight_number, rate));
+
+                match result{
+                    Ok(()) => println!("Waiting room is open! Admitting {} at once, then {} per second.", burst, per_second),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            },
+            ["draw", flight_number, seed] => {
+                let seed = if seed == "-" { Ok(None) } else { seed.parse::<u64>().map(Some) };
+
+                let (Ok(flight_number), Ok(seed)) = (flight_number.parse::<u32>(), seed) else {
+                    println!("Invalid input. Maybe a typoo? (Ex: draw 1 -)");
+                    continue;
+                };
+
+                match distr.draw_lottery(flight_number, seed){
+                    Ok(draw) => println!("Lottery drawn with seed {}! Winners: {} of {} entries, skipped over quota: {}.", 
+                        draw.seed, draw.winners.len(), draw.entries_num, draw.skipped.len()),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
             },
             ["exit"] => break,
             _ => println!("Invalid input. Maybe a typoo?"),
         }
     }
-}
\ No newline at end of file
+
+    match distr.shutdown(Duration::from_secs(3)).await{
+        Ok(summary) => println!("Server stopped! Requests finished during shutdown: {}, failed: {}, dropped: {}", 
+            summary.server.completed, summary.server.failed, summary.server.aborted),
+        Err(e) => eprintln!("Error: {}", e),
+    }
+}
+
+#[doc(hidden)]
+async fn gate_mode(){
+    clearscreen::clear().unwrap();
+    //welcome
+    println!("You are a Gate agent!");
+    //input flight number
+    print!("Please, input number of the boarded flight (Ex: 1) or simple type \"exit\"\n input: ");
+    stdout().flush().unwrap();
+    let mut gate = loop {
+        let mut flight_number = String::new();
+        stdin().read_line(&mut flight_number).unwrap();
+        flight_number.pop();//trailing newline removed
+
+        if &flight_number == "exit" {return ;}
+        if let Ok(flight_number) = flight_number.parse::<u32>(){
+            break Gate::new(flight_number).await.unwrap();
+        }
+        print!("Invalid input. Maybe a typoo?\n input: ");
+        stdout().flush().unwrap();
+    };
+    //input ip:port of the server 
+    print!("Please, input your distributor ip:port numbers (Ex: 127.0.0.1:8080) or simple type \"exit\"\nThis input is everything sensitive\n input: ");
+    stdout().flush().unwrap();
+    loop {
+        let mut serv_addr = String::new();
+        stdin().read_line(&mut serv_addr).unwrap();
+        serv_addr.pop();//trailing newline removed
+        
+        if &serv_addr == "exit" {return ;}
+        if gate.try_connect(&serv_addr).await.is_ok(){
+            println!("Successful connection!");
+            break;
+        }
+        println!("Connection failed. Maybe a typoo?");
+    }
+    print_help(GATE_COMMANDS);
+    loop {
+        print!("Scan a boarding pass or ticket code (hex) (help | exit) \n input: ");
+        stdout().flush().unwrap();
+
+        let mut input = String::new();
+        stdin().read_line(&mut input).unwrap();
+        let input = input.trim();
+        if input == "exit" { break; }
+        if input == "help" {
+            print_help(GATE_COMMANDS);
+            continue;
+        }
+
+        let Some(code) = parse_hex(input) else {
+            println!("Invalid input. Maybe a typoo?");
+            continue;
+        };
+
+        match gate.scan(&code).await{
+            Ok(scan) => println!("{:?} | seat: {} | boarded {} of {} checked in",
+                scan.result, scan.seat.as_deref().unwrap_or("-"), scan.count.boarded, scan.count.checked_in),
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+}
+
+#[doc(hidden)]
+const CLIENT_COMMANDS: &[(&str, &str)] = &[
+    ("fetch flights", "list flights with free seats"),
+    ("tickets", "list acquired tickets"),
+    ("sync tickets", "refresh acquired tickets from the distributor"),
+    ("id [passenger_id]", "set your passenger ID"),
+    ("ticketfor [flight_number]", "buy a ticket"),
+    ("ticketin [flight_number] [economy|premium|business]", "buy a ticket of the class"),
+    ("ticketpromo [flight_number] [code]", "buy a ticket with a promo code"),
+    ("hold [flight_number] [class|any]", "hold a seat at the quoted price"),
+    ("buy [hold_number]", "buy the held seat"),
+    ("enter [flight_number]", "enter the flight's lottery"),
+    ("lottery [flight_number]", "see your lottery status"),
+    ("queue [flight_number]", "join the flight's waiting room"),
+    ("checkin [ticket_number]", "check in and get a boarding pass"),
+    ("seat [ticket_number] [seat]", "change the seat of a ticket"),
+    ("transfer [ticket_number] [passenger_id]", "give a ticket to another passenger"),
+    ("claim [flight_number] [booking_ref] [secret]", "add a ticket given to you"),
+    ("cancel [ticket_number]", "cancel a ticket"),
+    ("trip [flight_number] [flight_number]...", "book a seat on every flight or none"),
+    ("exit", "leave"),
+];
+
+#[doc(hidden)]
+const SERVER_COMMANDS: &[(&str, &str)] = &[
+    ("see flights", "list flights"),
+    ("genflight [1..42]", "add a flight with the number of rows"),
+    ("quota [per_flight|-] [overall|-]", "set ticket quotas"),
+    ("lottery [flight] [secs]", "sell the flight by lottery, entries close in secs"),
+    ("draw [flight] [seed|-]", "draw the flight's lottery"),
+    ("waitroom [flight] [burst] [per_second]", "open or adjust the waiting room"),
+    ("window [flight] [opens_in_secs|-] [closes_in_secs|-]", "set the sale window"),
+    ("departure [flight] [in_secs|-]", "set departure time"),
+    ("fare [flight] [rows 1-3|seat A1] [class] [price]", "set fare of seats"),
+    ("pricing [max_markup] [velocity_markup]", "price by inventory"),
+    ("pricing off", "sell at fares"),
+    ("promo [code] [percent%|amount] [max_uses|-]", "add a promo code"),
+    ("promos", "list promo code usages"),
+    ("pubkey", "print the ticket signing public key"),
+    ("boarding [flight]", "see boarded and checked-in counts"),
+    ("manifest [flight] [csv|json] [path|-]", "export the passenger manifest"),
+    ("overbook [flight] [percent]", "sell standby tickets beyond capacity"),
+    ("resolve [flight]", "seat or bump standby tickets"),
+    ("close [flight]", "close the flight"),
+    ("gate [allow|revoke] [ip]", "manage gate addresses"),
+    ("exit", "stop the server"),
+];
+
+#[doc(hidden)]
+const GATE_COMMANDS: &[(&str, &str)] = &[
+    ("[code]", "scan a boarding pass or ticket code (hex)"),
+    ("exit", "leave"),
+];
+
+#[doc(hidden)]
+fn print_help(commands: &[(&str, &str)]){
+    let width = commands.iter().map(|(usage, _)| usage.len()).max().unwrap_or(0);
+
+    println!("Commands:");
+    for (usage, description) in commands{
+        println!("  {:width$} - {}", usage, description);
+    }
+}
+
+#[doc(hidden)]
+fn parse_class(class: &str) -> Option<FareClass>{
+    match class{
+        "economy" => Some(FareClass::Economy),
+        "premium" => Some(FareClass::Premium),
+        "business" => Some(FareClass::Business),
+        _ => None,
+    }
+}
+
+#[doc(hidden)]
+fn price_str(price: u64) -> String{
+    format!("{}.{:02}", price / 100, price % 100)
+}
+
+#[doc(hidden)]
+fn hex_str(bytes: &[u8]) -> String{
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[doc(hidden)]
+fn parse_hex(hex: &str) -> Option<Vec<u8>>{
+    if !hex.len().is_multiple_of(2) { return None; }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
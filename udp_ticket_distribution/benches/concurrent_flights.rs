@@ -0,0 +1,93 @@
+//! Throughput of seat allocation with many concurrently sold flights
+//!
+//! Compares `FlightStorage` (lock per flight) with a single
+//! `RwLock` over all flights. Run with `cargo bench`
+//!
+//! Lock contention only shows up with several cores available,
+//! on a single core both variants run threads one after another
+
+use std::sync::{Arc, Barrier, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use udp_ticket_distribution::*;
+
+const FLIGHTS_NUM: u32 = 64;
+const ROWS: u8 = 42;
+const THREADS_NUM: u32 = 8;
+const ROUNDS: u32 = 50;
+
+fn make_flight(num: u32) -> FlightDB{
+    let mut seats = Vec::new();
+    for row in 1..=ROWS{
+        for letter in "ABCDEF".chars(){
+            seats.push(format!("{}{}",letter,row));
+        }
+    }
+
+    FlightDB::new(num, seats)
+}
+
+/// Every thread sells its own subset of flights until they are sold out
+fn run_threads(sell: impl Fn(u32) -> Option<String> + Send + Sync + 'static) -> Duration{
+    let sell = Arc::new(sell);
+    let barrier = Arc::new(Barrier::new(THREADS_NUM as usize + 1));
+
+    let handles: Vec<_> = (0..THREADS_NUM).map(|t| {
+        let sell = sell.clone();
+        let barrier = barrier.clone();
+
+        thread::spawn(move || {
+            barrier.wait();
+            for num in (1..=FLIGHTS_NUM).filter(|n| n % THREADS_NUM == t){
+                while sell(num).is_some() {}
+            }
+        })
+    }).collect();
+
+    barrier.wait();
+    let start = Instant::now();
+
+    for h in handles { h.join().unwrap(); }
+
+    start.elapsed()
+}
+
+fn per_flight_locks() -> Duration{
+    let storage = Arc::new(FlightStorage::new());
+    for num in 1..=FLIGHTS_NUM{
+        storage.push_with(|_| make_flight(num));
+    }
+
+    run_threads(move |num| storage.take_seat(num))
+}
+
+fn global_lock() -> Duration{
+    let storage = Arc::new(RwLock::new(
+        (1..=FLIGHTS_NUM).map(make_flight).collect::<Vec<_>>()
+    ));
+
+    run_threads(move |num| {
+        storage.write().unwrap()
+            .iter_mut()
+            .find(|f| f.info.num == num)?
+            .take_seat()
+    })
+}
+
+fn report(name: &str, bench: fn() -> Duration){
+    let total: Duration = (0..ROUNDS).map(|_| bench()).sum();
+    let sold = (FLIGHTS_NUM * ROWS as u32 * 6 * ROUNDS) as f64;
+
+    println!("{:<18} {:>10.0} tickets/s  ({:?} per round)",
+        name, sold / total.as_secs_f64(), total / ROUNDS);
+}
+
+fn main(){
+    let cores = thread::available_parallelism().map_or(1, |n| n.get());
+
+    println!("{} flights x {} seats, {} threads on {} cores, {} rounds",
+        FLIGHTS_NUM, ROWS as u32 * 6, THREADS_NUM, cores, ROUNDS);
+
+    report("global RwLock", global_lock);
+    report("per-flight locks", per_flight_locks);
+}
@@ -0,0 +1,287 @@
+use std::io::{Error, ErrorKind};
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+use super::*;
+use super::msg::encode;
+
+/// Check-in period relative to departure
+/// 
+/// Flights without departure time accept check-in any time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckInWindow{
+    /// Check-in opens this long before departure
+    pub opens_before: Duration,
+    /// Check-in closes this long before departure
+    pub closes_before: Duration,
+}
+
+impl Default for CheckInWindow{
+    fn default() -> Self{
+        CheckInWindow { opens_before: Duration::from_secs(24*3600), closes_before: Duration::from_secs(45*60) }
+    }
+}
+
+impl CheckInWindow{
+    /// Returns rejection if check-in for the departure is not open at `now`
+    pub(crate) fn check(&self, departure: Option<SystemTime>, now: SystemTime) -> std::result::Result<(), Rejection>{
+        let Some(departure) = departure else { return Ok(()); };
+
+        let opens = departure.checked_sub(self.opens_before).unwrap_or(SystemTime::UNIX_EPOCH);
+        let closes = departure.checked_sub(self.closes_before).unwrap_or(SystemTime::UNIX_EPOCH);
+
+        if now < opens { return Err(Rejection::CheckInNotOpen{ opens }); }
+        if now >= closes { return Err(Rejection::CheckInClosed); }
+
+        Ok(())
+    }
+}
+
+/// Check-in of a sold ticket
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckIn{
+    /// Boarding group, lower groups board first
+    pub group: u8,
+    /// Order of check-in within the flight, starting from 1
+    pub sequence: u32,
+    pub checked_in_at: SystemTime,
+}
+
+impl CheckIn{
+    /// Returns boarding group of the fare class
+    pub fn group_for(class: FareClass) -> u8{
+        match class{
+            FareClass::Business => 1,
+            FareClass::Premium => 2,
+            FareClass::Economy => 3,
+        }
+    }
+}
+
+/// Boarding pass given on check-in
+/// 
+/// Signed ticket inside proves the pass, see `TicketVerifier`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoardingPass{
+    pub flight_num: u32,
+    pub seat: String,
+    pub booking_ref: String,
+    pub passenger_id: Option<String>,
+    pub departure: Option<SystemTime>,
+    pub check_in: CheckIn,
+    pub ticket: SignedTicket,
+}
+
+impl BoardingPass{
+    /// Returns pass serialized for printing as a code scanned at the gate
+    pub fn to_bytes(&self) -> Vec<u8>{ encode(self) }
+
+    /// Returns pass from bytes made by `BoardingPass::to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self>{
+        serde_cbor::from_slice(bytes)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Boarding pass is malformed"))
+    }
+}
+
+/// Returns signed ticket of a scanned boarding pass or ticket code
+pub(crate) fn scanned_ticket(bytes: &[u8]) -> Option<SignedTicket>{
+    BoardingPass::from_bytes(bytes)
+        .map(|pass| pass.ticket)
+        .or_else(|_| SignedTicket::from_bytes(bytes))
+        .ok()
+}
+
+/// Result of a code scanned at the gate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScanResult{
+    /// Passenger is boarded now
+    Accept,
+    /// Ticket was already scanned
+    AlreadyBoarded,
+    /// Ticket is valid for another flight
+    WrongFlight,
+    /// Code is not a valid checked-in ticket of an open flight
+    Invalid,
+}
+
+/// Number of boarded and checked-in passengers of a flight
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoardingCount{
+    pub boarded: usize,
+    pub checked_in: usize,
+}
+
+/// Answer to a gate scan
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GateScan{
+    pub result: ScanResult,
+    /// Seat of the scanned ticket, if the ticket is valid
+    pub seat: Option<String>,
+    /// Count of the flight after the scan
+    pub count: BoardingCount,
+}
+
+/// Stage a sold ticket has reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoardingStatus{
+    Sold,
+    CheckedIn,
+    Boarded,
+}
+
+impl BoardingStatus{
+    /// Returns status as written in exported manifests
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            BoardingStatus::Sold => "sold",
+            BoardingStatus::CheckedIn => "checked_in",
+            BoardingStatus::Boarded => "boarded",
+        }
+    }
+}
+
+/// File format of an exported manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat{
+    Csv,
+    Json,
+}
+
+/// Passenger of a flight manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry{
+    pub seat: String,
+    pub booking_ref: String,
+    pub passenger_id: Option<String>,
+    /// Address the ticket was bought from, unspecified for transferred tickets
+    pub ip: IpAddr,
+    pub status: BoardingStatus,
+}
+
+/// List of the flight's passengers, sorted by seat
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest{
+    pub flight_num: u32,
+    pub departure: Option<SystemTime>,
+    /// `true` if the flight is closed and the manifest is final
+    pub closed: bool,
+    pub passengers: Vec<ManifestEntry>,
+}
+
+impl Manifest{
+    /// Returns number of passengers with the status
+    pub fn count(&self, status: BoardingStatus) -> usize{
+        self.passengers.iter().filter(|p| p.status == status).count()
+    }
+
+    /// Returns manifest in the format
+    pub fn export(&self, format: ManifestFormat) -> String{
+        match format{
+            ManifestFormat::Csv => self.to_csv(),
+            ManifestFormat::Json => self.to_json(),
+        }
+    }
+
+    /// Returns manifest as CSV with a header line, one line per sold seat
+    pub fn to_csv(&self) -> String{
+        let mut csv = String::from("flight_num,seat,passenger_id,ip,booking_ref,status\n");
+
+        for p in &self.passengers{
+            csv += &format!("{},{},{},{},{},{}\n",
+                self.flight_num,
+                csv_field(&p.seat),
+                csv_field(p.passenger_id.as_deref().unwrap_or("")),
+                p.ip,
+                csv_field(&p.booking_ref),
+                p.status.as_str(),
+            );
+        }
+
+        csv
+    }
+
+    /// Returns manifest as pretty printed JSON
+    pub fn to_json(&self) -> String{
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+/// Quotes the field if it has commas, quotes or line breaks
+fn csv_field(field: &str) -> String{
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn check_in_window(){
+        let window = CheckInWindow{ opens_before: Duration::from_secs(100), closes_before: Duration::from_secs(10) };
+        let departure = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+
+        assert_eq!(window.check(Some(departure), at(899)), Err(Rejection::CheckInNotOpen{ opens: at(900) }));
+        assert_eq!(window.check(Some(departure), at(900)), Ok(()));
+        assert_eq!(window.check(Some(departure), at(990)), Err(Rejection::CheckInClosed));
+        assert_eq!(window.check(None, at(0)), Ok(()));
+    }
+
+    #[test]
+    fn scanned_codes(){
+        let signer = super::super::ticket::TicketSigner::default();
+        let mut flight = FlightDB::new(1, vec!["A1".to_string()]);
+        let owner = TicketOwner{ passenger_id: None, ip: IpAddr::from([127, 0, 0, 1]) };
+        let sold = flight.sell_seat(owner).unwrap();
+        let ticket = signer.sign(1, &sold);
+
+        let pass = BoardingPass{
+            flight_num: 1,
+            seat: sold.seat,
+            booking_ref: sold.booking_ref,
+            passenger_id: None,
+            departure: None,
+            check_in: CheckIn{ group: 3, sequence: 1, checked_in_at: SystemTime::UNIX_EPOCH },
+            ticket: ticket.clone(),
+        };
+
+        assert_eq!(scanned_ticket(&pass.to_bytes()), Some(ticket.clone()));
+        assert_eq!(scanned_ticket(&ticket.to_bytes()), Some(ticket));
+        assert_eq!(scanned_ticket(b"garbage"), None);
+    }
+
+    #[test]
+    fn manifest_export(){
+        let entry = |seat: &str, passenger_id: Option<&str>, status| ManifestEntry{
+            seat: seat.to_string(),
+            booking_ref: "ABC234".to_string(),
+            passenger_id: passenger_id.map(String::from),
+            ip: IpAddr::from([127, 0, 0, 1]),
+            status,
+        };
+
+        let manifest = Manifest{
+            flight_num: 7,
+            departure: None,
+            closed: false,
+            passengers: vec![
+                entry("A1", Some("Doe, \"J\""), BoardingStatus::Boarded),
+                entry("B1", None, BoardingStatus::CheckedIn),
+            ],
+        };
+
+        assert_eq!(manifest.export(ManifestFormat::Csv), 
+            "flight_num,seat,passenger_id,ip,booking_ref,status\n\
+            7,A1,\"Doe, \"\"J\"\"\",127.0.0.1,ABC234,boarded\n\
+            7,B1,,127.0.0.1,ABC234,checked_in\n");
+
+        let json: serde_json::Value = serde_json::from_str(&manifest.export(ManifestFormat::Json)).unwrap();
+        assert_eq!(json["flight_num"], 7);
+        assert_eq!(json["passengers"][0]["passenger_id"], "Doe, \"J\"");
+        assert_eq!(json["passengers"][1]["status"], "checked_in");
+    }
+}
@@ -1,43 +1,401 @@
-use std::sync::{Arc, RwLock};
+use std::io::{Error, ErrorKind};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use super::*;
+use super::rng::SeededRng;
+use super::limits::RateLimiter;
+use super::logic::Logic;
+use super::ticket::{new_booking_ref, new_ticket_secret};
+use super::overbook::{allowance, standby_seat};
 
 /// Implements distributor's role in the process of acquiring tickets
 pub struct Distributor{
     udp_server: ServerSide,
-    db_storage: Arc<RwLock<Vec<FlightDB>>>,  //available flights, sold flights and tickets
+    logic: Logic,  //available flights, sold flights and tickets
+    shard: Option<ShardConfig>,
+    rate_limiter: RateLimiter,
 }
 
 impl Distributor{
     /// Returns `Result<Passenger>` if socket binding was successful
     pub async fn new() -> Result<Self>{
-        let mut distr = Distributor{
-            udp_server: ServerSide::new_with_address(Auto).await?,
-            db_storage: RwLock::new( Vec::new() ).into(),
-        }; 
-
-        distr.set_logic_fn();
-        distr.udp_server.start();
-        
-        Ok(distr)
+        Ok(Self::with_server( ServerSide::new_with_address(Auto).await? ))
     }
 
     /// Returns `Result<Passenger>` if socket binding was successful
     pub async fn new_with_address(addr: String) -> Result<Self>{
+        Ok(Self::with_server( ServerSide::new_with_address( Manual(addr) ).await? ))
+    }
+
+    fn with_server(udp_server: ServerSide) -> Self{
         let mut distr = Distributor{
-            udp_server: ServerSide::new_with_address( Manual(addr) ).await?,
-            db_storage: RwLock::new( Vec::new() ).into(),
+            udp_server,
+            logic: Logic::default(),
+            shard: None,
+            rate_limiter: RateLimiter::default(),
         }; 
 
         distr.set_logic_fn();
+        distr.udp_server.add_layer(distr.rate_limiter.clone());
         distr.udp_server.start();
-        
-        Ok(distr)
+
+        distr
     }
 
     fn set_logic_fn(&mut self) {
-        let logic_fn = self.make_logic_fn();
+        let logic = self.logic.clone();
+
+        self.udp_server.set_async_processing_fn(move |packet, ctx|
+            std::future::ready(logic.process(packet, &ctx)));
+    }
+
+    /// Adds a `Middleware` (logging, metrics, validation...) around distributor's logic
+    /// 
+    /// Layers are called in the order they were added, 
+    /// applied without restart of the distributor
+    pub fn add_layer(&mut self, layer: impl Middleware){
+        self.udp_server.add_layer(layer);
+    }
+
+    /// Sets token bucket limit for a request type, `None` removes the limit
+    /// 
+    /// Buckets are kept per source IP and, when request has it, per passenger ID.
+    /// Limited requests are answered with `RateLimited` and time to wait.
+    /// Can be changed on a running distributor
+    pub fn set_rate_limit(&self, request: PacketRequest, limit: Option<RateLimit>){
+        self.rate_limiter.set_limit(request, limit);
+    }
+
+    /// Returns limit of a request type
+    pub fn rate_limit(&self, request: PacketRequest) -> Option<RateLimit>{
+        self.rate_limiter.limit(request)
+    }
+
+    /// Sets limits of tickets per passenger and per address
+    /// 
+    /// Requests over the limit are answered with `QuotaExceeded`.
+    /// Can be changed on a running distributor, 
+    /// already issued tickets are counted against new rules
+    pub fn set_quota(&self, rules: QuotaRules){
+        self.logic.quotas.set_rules(rules);
+    }
+
+    /// Returns current quota rules
+    pub fn quota(&self) -> QuotaRules{ self.logic.quotas.rules() }
+
+    /// Switches the flight to lottery mode for `entry_window`
+    /// 
+    /// Passengers register with `EnterLottery` during the window,
+    /// then `draw_lottery` gives the seats to random entrants
+    pub fn start_lottery(&self, flight_num: u32, entry_window: Duration) -> Result<()>{
+        let flight = self.flight(flight_num)?;
+        let mut flight = flight.lock().unwrap();
+
+        if flight.lottery.is_some() {
+            return Err(Error::new(ErrorKind::AlreadyExists, "Flight already has a lottery"));
+        }
+
+        flight.lottery = Some(Lottery::new(SystemTime::now() + entry_window));
+
+        Ok(())
+    }
+
+    /// Draws the lottery after its entry window is closed
+    /// 
+    /// The same seed and entries always give the same winners,
+    /// without seed it is made from current time. 
+    /// Returned `LotteryDraw` holds the seed for audits, see `LotteryDraw::verify`.
+    /// Entrants over their ticket quota are skipped, winners pay the current pricing policy's price
+    pub fn draw_lottery(&self, flight_num: u32, seed: Option<u64>) -> Result<LotteryDraw>{
+        let flight = self.flight(flight_num)?;
+        let mut flight = flight.lock().unwrap();
+
+        let Some(mut lottery) = flight.lottery.take() else {
+            return Err(Error::new(ErrorKind::NotFound, "Flight has no lottery"));
+        };
+
+        let check = if lottery.draw.is_some() {
+            Err(Error::new(ErrorKind::AlreadyExists, "Lottery is already drawn"))
+        } else if SystemTime::now() < lottery.entries_close {
+            Err(Error::new(ErrorKind::WouldBlock, "Lottery entries are still open"))
+        } else {
+            Ok(())
+        };
+
+        if let Err(e) = check {
+            flight.lottery = Some(lottery);
+            return Err(e);
+        }
+
+        let seed = seed.unwrap_or_else(SeededRng::time_seed);
+        let draw = lottery.draw(&mut flight, seed, &self.logic.pricing, SystemTime::now(), |flight, owner| {
+            let keys = owner.keys();
+            self.logic.check_flight_quota(flight, &keys).is_ok() && self.logic.quotas.reserve(&keys).is_ok()
+        });
+        flight.lottery = Some(lottery);
+
+        Ok(draw)
+    }
+
+    /// Sets time when ticket sales of the flight open and close, `None` leaves the side unbounded
+    /// 
+    /// Ticket, lottery and queue requests outside of the window are answered with `SaleNotOpen` or `SaleClosed`,
+    /// `GetFlights` reports the window so passengers can count down to the opening
+    pub fn set_sale_window(&self, flight_num: u32, opens: Option<SystemTime>, closes: Option<SystemTime>) -> Result<()>{
+        if let (Some(opens), Some(closes)) = (opens, closes) {
+            if closes <= opens {
+                return Err(Error::new(ErrorKind::InvalidInput, "Sales have to open before they close"));
+            }
+        }
+
+        let flight = self.flight(flight_num)?;
+        let mut flight = flight.lock().unwrap();
+
+        flight.info.sale_opens = opens;
+        flight.info.sale_closes = closes;
+
+        Ok(())
+    }
+
+    /// Sets class and price of the seats, later fares override earlier ones
+    /// 
+    /// Seats without a fare are economy for free.
+    /// Already sold tickets keep their fares.
+    /// Fails with `NotFound` if a single seat is not a seat of the flight
+    pub fn set_fare(&self, flight_num: u32, seats: SeatRange, fare: Fare) -> Result<()>{
+        let flight = self.flight(flight_num)?;
+        let mut flight = flight.lock().unwrap();
+
+        if let SeatRange::Seat(seat) = &seats {
+            if !flight.has_seat(seat) {
+                return Err(Error::new(ErrorKind::NotFound, format!("Flight has no seat {}", seat)));
+            }
+        }
+
+        flight.set_fare(seats, fare);
+
+        Ok(())
+    }
+
+    /// Sets policy computing seat prices from fares, inventory and demand
+    /// 
+    /// Applied to tickets sold and held after the call,
+    /// held seats keep the price they were quoted with
+    pub fn set_pricing(&self, policy: impl PricingPolicy){
+        self.logic.pricing.set_policy(Some(Arc::new(policy)));
+    }
+
+    /// Removes pricing policy, seats are sold at their fares
+    pub fn clear_pricing(&self){
+        self.logic.pricing.set_policy(None);
+    }
+
+    /// Sets how long a seat stays held after `HoldTicket`, 60 seconds by default
+    pub fn set_hold_time(&self, hold_time: Duration){
+        self.logic.pricing.set_hold_time(hold_time);
+    }
+
+    /// Returns how long a seat stays held
+    pub fn hold_time(&self) -> Duration{ self.logic.pricing.hold_time() }
+
+    /// Sets departure time of the flight, reported by `GetFlights` and used by pricing policies
+    pub fn set_departure(&self, flight_num: u32, departure: Option<SystemTime>) -> Result<()>{
+        let flight = self.flight(flight_num)?;
+
+        flight.lock().unwrap().info.departure = departure;
+
+        Ok(())
+    }
+
+    /// Adds promo code, replaced code keeps its usage count
+    /// 
+    /// Code is given with `RequestTicket` or `BuyHeld`,
+    /// it is validated and counted together with the sale
+    pub fn add_promo(&self, code: impl Into<String>, promo: PromoCode){
+        self.logic.promos.insert(code.into(), promo);
+    }
+
+    /// Removes promo code, tickets bought with it keep their fares
+    pub fn remove_promo(&self, code: &str) -> Option<PromoCode>{
+        self.logic.promos.remove(code)
+    }
+
+    /// Returns number of tickets bought with the code
+    pub fn promo_usage(&self, code: &str) -> Option<u32>{
+        self.logic.promos.uses(code)
+    }
+
+    /// Returns usage of every defined code
+    pub fn promo_usages(&self) -> HashMap<String, u32>{
+        self.logic.promos.usage()
+    }
+
+    /// Returns Ed25519 public key tickets are signed with, see `TicketVerifier`
+    pub fn public_key(&self) -> [u8; 32]{ self.logic.signer.public_key() }
+
+    /// Returns current public key followed by the keys it replaced, all of them are accepted at the gate
+    pub fn public_keys(&self) -> Vec<[u8; 32]>{ self.logic.signer.public_keys() }
+
+    /// Sets Ed25519 secret key tickets are signed with
+    /// 
+    /// Distributor generates a new key on start, 
+    /// set a stored one to keep issued tickets verifiable after restarts.
+    /// Tickets signed with replaced keys still board, see `Distributor::public_keys`
+    pub fn set_signing_key(&self, secret_key: &[u8; 32]){
+        self.logic.signer.set_key(secret_key);
+    }
+
+    /// Returns newly signed copy of a sold ticket
+    pub fn sign_ticket(&self, flight_num: u32, booking_ref: &str) -> Result<SignedTicket>{
+        let flight = self.flight(flight_num)?;
+        let flight = flight.lock().unwrap();
 
-        self.udp_server.set_processing_fn(logic_fn);
+        let ticket = flight.ticket_by_ref(booking_ref)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "Ticket not found"))?;
+
+        Ok(self.logic.signer.sign(flight_num, ticket))
+    }
+
+    /// Sets check-in period relative to departure, see `Distributor::set_departure`
+    pub fn set_check_in_window(&self, window: CheckInWindow){
+        *self.logic.check_in.lock().unwrap() = window;
+    }
+
+    /// Returns check-in period
+    pub fn check_in_window(&self) -> CheckInWindow{ *self.logic.check_in.lock().unwrap() }
+
+    /// Accepts `GateScan` requests from the address
+    /// 
+    /// Scans from other addresses are answered with `GateNotAllowed`, no address is allowed by default
+    pub fn allow_gate(&self, ip: IpAddr){
+        self.logic.gates.lock().unwrap().insert(ip);
+    }
+
+    /// Stops accepting `GateScan` requests from the address, `false` if it was not allowed
+    pub fn revoke_gate(&self, ip: IpAddr) -> bool{
+        self.logic.gates.lock().unwrap().remove(&ip)
+    }
+
+    /// Returns number of checked-in passengers of the flight
+    pub fn checked_in_count(&self, flight_num: u32) -> Result<usize>{
+        Ok(self.flight(flight_num)?.lock().unwrap().checked_in_count())
+    }
+
+    /// Returns number of boarded and checked-in passengers of the flight
+    pub fn boarding_count(&self, flight_num: u32) -> Result<BoardingCount>{
+        Ok(self.flight(flight_num)?.lock().unwrap().boarding_count())
+    }
+
+    /// Returns current manifest of the flight
+    pub fn manifest(&self, flight_num: u32) -> Result<Manifest>{
+        Ok(self.flight(flight_num)?.lock().unwrap().manifest())
+    }
+
+    /// Returns current manifest of the flight as CSV or JSON
+    pub fn export_manifest(&self, flight_num: u32, format: ManifestFormat) -> Result<String>{
+        Ok(self.manifest(flight_num)?.export(format))
+    }
+
+    /// Allows to sell standby tickets beyond capacity, 
+    /// `percent` of the flight's seats, rounded down
+    /// 
+    /// Returns number of allowed standby tickets
+    pub fn set_overbooking(&self, flight_num: u32, percent: u8) -> Result<u32>{
+        let flight = self.flight(flight_num)?;
+        let mut flight = flight.lock().unwrap();
+
+        let capacity = flight.seats.len() + flight.holds.len() + flight.sold.iter().filter(|t| !t.standby).count();
+        flight.overbooking = allowance(capacity, percent);
+
+        Ok(flight.overbooking)
+    }
+
+    /// Gives free seats to standby tickets and denies boarding to the rest
+    /// 
+    /// Denied tickets are refunded: they leave sales, quotas and promo code uses
+    pub fn resolve_overbooking(&self, flight_num: u32) -> Result<OverbookingResolution>{
+        let flight = self.flight(flight_num)?;
+        let resolution = flight.lock().unwrap().resolve_overbooking(SystemTime::now());
+
+        for ticket in &resolution.bumped{
+            self.logic.refund(ticket);
+        }
+
+        Ok(resolution)
+    }
+
+    /// Closes the flight and returns its final manifest
+    /// 
+    /// Closed flight does not sell, check in or board anymore
+    pub fn close_flight(&self, flight_num: u32) -> Result<Manifest>{
+        let flight = self.flight(flight_num)?;
+        let mut flight = flight.lock().unwrap();
+
+        flight.closed = true;
+        flight.holds.clear();
+
+        Ok(flight.manifest())
+    }
+
+    /// Puts a waiting room in front of the flight's sale
+    /// 
+    /// Passengers get a place with `JoinQueue`, poll it with `QueuePosition`
+    /// and may request a ticket only after admission.
+    /// `rate` sets how many passengers are admitted at once and then every second
+    pub fn open_waiting_room(&self, flight_num: u32, rate: RateLimit) -> Result<()>{
+        let flight = self.flight(flight_num)?;
+        let mut flight = flight.lock().unwrap();
+
+        if flight.waiting_room.is_some() {
+            return Err(Error::new(ErrorKind::AlreadyExists, "Flight already has a waiting room"));
+        }
+
+        flight.waiting_room = Some(WaitingRoom::new(rate, Instant::now()));
+
+        Ok(())
+    }
+
+    /// Changes admission rate of a running waiting room
+    pub fn set_admission_rate(&self, flight_num: u32, rate: RateLimit) -> Result<()>{
+        let flight = self.flight(flight_num)?;
+        let mut flight = flight.lock().unwrap();
+
+        match &mut flight.waiting_room{
+            Some(room) => { room.set_rate(rate, Instant::now()); Ok(()) },
+            None => Err(Error::new(ErrorKind::NotFound, "Flight has no waiting room")),
+        }
+    }
+
+    /// Removes the waiting room, everybody may buy tickets again
+    /// 
+    /// Returns the room with its queue
+    pub fn close_waiting_room(&self, flight_num: u32) -> Result<WaitingRoom>{
+        let flight = self.flight(flight_num)?;
+        let mut flight = flight.lock().unwrap();
+
+        flight.waiting_room.take()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "Flight has no waiting room"))
+    }
+
+    fn flight(&self, flight_num: u32) -> Result<Arc<Mutex<FlightDB>>>{
+        self.logic.db_storage.flight(flight_num)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "Flight not found"))
+    }
+
+    /// Stops accepting requests, waits for in-flight ones up to `deadline`
+    /// and returns final state of the storage
+    /// 
+    /// Storage is kept in memory, so returned flights are the only
+    /// way to save sales results after the shutdown
+    pub async fn shutdown(&mut self, deadline: Duration) -> Result<DistributorSummary>{
+        let server = self.udp_server.shutdown(deadline).await?;
+
+        Ok(DistributorSummary{
+            server,
+            flights: self.logic.db_storage.snapshot(),
+        })
     }
 
     /// Returns socket address
@@ -45,27 +403,32 @@ impl Distributor{
         self.udp_server.local_addr().to_string()
     }
 
-    /// Returns `Arc<RwLock<Vec<FlightDB>>>` which  work as storage of flights
-    pub fn db_storage(&self) -> Arc<RwLock<Vec<FlightDB>>>{
-        self.db_storage.clone()
+    /// Makes distributor a member of a sharded setup
+    /// 
+    /// Newly generated flights will get only numbers routed to this shard,
+    /// so `PassengerRouter` with the same `ShardMap` will find them.
+    /// Returns error if the config is invalid, see `ShardConfig::validate`
+    pub fn set_shard(&mut self, shard: ShardConfig) -> Result<()>{
+        shard.validate()?;
+        self.shard = Some(shard);
+
+        Ok(())
+    }
+
+    /// Returns shard config if distributor is a part of sharded setup
+    pub fn shard(&self) -> Option<&ShardConfig>{ self.shard.as_ref() }
+
+    /// Returns `Arc<FlightStorage>` which  work as storage of flights
+    pub fn db_storage(&self) -> Arc<FlightStorage>{
+        self.logic.db_storage.clone()
     }
 
 
     /// Creates new flight with rows of seats in range of `1..=42`
     /// 
-    /// Variable `rows` will be shifted to the closest value in the range if it is not
-    pub async fn gen_fake_flight(&self, rows: u8){
-        let mut lock_db = self.db_storage.write().unwrap();
-
-        let mut num = 1;
-        if let Some(max_num) = 
-                lock_db
-                    .iter()
-                    .map(|f| f.info.num)
-                    .max(){
-            num+=max_num;
-        };
-
+    /// Variable `rows` will be shifted to the closest value in the range if it is not.
+    /// Returns number of the new flight, `None` if no free number near the biggest one is routed to this shard
+    pub async fn gen_fake_flight(&self, rows: u8) -> Option<u32>{
         let rows = match rows{
             43.. => 42,
             0=> 1,
@@ -78,59 +441,119 @@ impl Distributor{
                 seats.push(format!("{}{}",letter,row));
             }
         }
-        
-        lock_db.push(FlightDB{
-            info: FlightInfo { num: num, seats_num: rows*6 },
-            seats
-        });
-    }
 
-    fn make_logic_fn(&mut self) -> impl Fn(UdpPacket) -> UdpPacket {
-        let shared_arc = self.db_storage.clone();
+        self.logic.db_storage.try_push_with(|max_num| {
+            let first = max_num.unwrap_or(0).checked_add(1)?;
 
-        let closure = move | packet:UdpPacket | -> UdpPacket {
-            let db_storage = shared_arc.clone();
+            let num = match &self.shard{
+                Some(shard) => (first..=first.saturating_add(MAX_SHARD_SEARCH)).find(|&n| shard.owns(n))?,
+                None => first,
+            };
 
-            match packet.request(){
-                PacketRequest::Ping => packet.set_response(PacketResponse::Ok),
-                PacketRequest::GetFlights => {
-                    let lock = db_storage.read().unwrap();
+            Some(FlightDB::new(num, seats))
+        })
+    }
+}
 
-                    let flights: Vec<FlightInfo> = lock.iter().map(|f| f.info ).collect();
+/// Number of flight numbers `gen_fake_flight` tries to find one routed to its shard
+const MAX_SHARD_SEARCH: u32 = 1 << 16;
 
-                    let packet = packet
-                        .set_response(PacketResponse::Ok)
-                        .set_data(&convert_to_bytes(&flights));
+/// Result of `Distributor::shutdown`
+#[derive(Clone)]
+pub struct DistributorSummary{
+    /// Summary of in-flight requests processing
+    pub server: ShutdownSummary,
+    /// Final state of every flight
+    pub flights: Vec<FlightDB>,
+}
 
-                    packet
-                },
-                PacketRequest::RequestTicket => {
-                    let flight_num = u32::from_le_bytes( packet.try_retrieve_data().unwrap()[0..4].try_into().unwrap() );
+/// Identity of a ticket buyer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TicketOwner{
+    /// Passenger ID, if passenger sent it
+    pub passenger_id: Option<String>,
+    /// Source IP of the request, unspecified for owners known by ID alone
+    pub ip: IpAddr,
+}
 
-                    let mut lock = db_storage.write().unwrap();
+impl TicketOwner{
+    /// Returns owner known by passenger ID alone, e.g. recipient of a transferred ticket
+    pub fn by_id(passenger_id: String) -> Self{
+        TicketOwner{ passenger_id: Some(passenger_id), ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED) }
+    }
 
-                    if let Some(flight) = lock.iter_mut().find(|f| (f.info.num == flight_num && f.info.seats_num > 0) ){
+    /// Returns keys the owner is counted by in quotas and limits
+    pub(crate) fn keys(&self) -> Vec<String>{
+        let mut keys = Vec::new();
 
-                        let ticket = flight.seats.pop().unwrap();
+        if !self.ip.is_unspecified() {
+            keys.push(format!("ip:{}", self.ip));
+        }
 
-                        flight.info.seats_num-=1;
+        if let Some(id) = &self.passenger_id {
+            keys.push(format!("id:{}", id));
+        }
 
-                        let packet = packet
-                            .set_response(PacketResponse::Ok)
-                            .set_data(ticket.as_bytes());
+        keys
+    }
 
-                        return packet;
-                    }
-                    else{
-                        let packet = packet
-                            .set_response(PacketResponse::TicketsSold);
+    /// Returns `true` if both are the same passenger
+    /// 
+    /// Passengers are compared by ID, passengers without ID by IP
+    pub fn same_as(&self, other: &TicketOwner) -> bool{
+        match (&self.passenger_id, &other.passenger_id){
+            (Some(id), Some(other_id)) => id == other_id,
+            (None, None) => self.ip == other.ip,
+            _ => false,
+        }
+    }
+}
 
-                        return packet;
-                    }
-                },
-            }
-        };
-        closure
+/// Seat sold to an owner
+#[derive(Debug, Clone)]
+pub struct SoldTicket{
+    /// Seat code, e.g. "A1"
+    pub seat: String,
+    /// Who bought the seat
+    pub owner: TicketOwner,
+    /// Class and price the seat was sold for
+    pub fare: Fare,
+    /// Time of the sale
+    pub sold_at: SystemTime,
+    /// Promo code the fare was discounted with
+    pub promo_code: Option<String>,
+    /// Booking reference, unique within the flight
+    pub booking_ref: String,
+    /// Check-in of the ticket, `None` before check-in
+    pub check_in: Option<CheckIn>,
+    /// Time the passenger was scanned at the gate
+    pub boarded_at: Option<SystemTime>,
+    /// Previous changes of the owner, oldest first
+    pub transfers: Vec<TicketTransfer>,
+    /// Ticket was sold beyond capacity and waits for a free seat
+    pub standby: bool,
+    /// Number of owner or seat changes, signed into the ticket
+    pub revision: u32,
+    /// Secret proving ownership, given only to the owner
+    pub secret: u64,
+}
+
+/// Change of a ticket's owner
+#[derive(Debug, Clone)]
+pub struct TicketTransfer{
+    pub from: TicketOwner,
+    pub to: TicketOwner,
+    pub at: SystemTime,
+}
+
+impl SoldTicket{
+    /// Returns stage the ticket has reached
+    pub fn status(&self) -> BoardingStatus{
+        match (self.check_in, self.boarded_at){
+            (_, Some(_)) => BoardingStatus::Boarded,
+            (Some(_), None) => BoardingStatus::CheckedIn,
+            (None, None) => BoardingStatus::Sold,
+        }
     }
 }
 
@@ -141,4 +564,543 @@ pub struct FlightDB{
     pub info: FlightInfo,
     /// Available seats 
     pub seats: Vec<String>,
-}
\ No newline at end of file
+    /// Sold seats with their owners
+    pub sold: Vec<SoldTicket>,
+    /// Lottery of seats, if flight is sold by lottery
+    pub lottery: Option<Lottery>,
+    /// Queue passengers wait in before they may buy
+    pub waiting_room: Option<WaitingRoom>,
+    /// Fares of seats, later ones override earlier ones
+    pub fares: Vec<(SeatRange, Fare)>,
+    /// Seats taken out of sale at a quoted fare
+    pub holds: Vec<SeatHold>,
+    /// Flight is closed for sales, check-in and boarding
+    pub closed: bool,
+    /// Number of standby tickets which may be sold beyond capacity
+    pub overbooking: u32,
+    /// Standby tickets denied boarding by `Distributor::resolve_overbooking`
+    pub denied: Vec<SoldTicket>,
+    last_hold_id: u64,
+    last_standby: u32,
+    last_sequence: u32,
+}
+
+impl FlightDB{
+    /// Returns flight with specified seats available
+    pub fn new(num: u32, seats: Vec<String>) -> Self{
+        FlightDB{
+            info: FlightInfo { 
+                num, 
+                seats_num: seats.len().try_into().unwrap_or(u8::MAX), 
+                ..Default::default() 
+            },
+            seats,
+            sold: Vec::new(),
+            lottery: None,
+            waiting_room: None,
+            fares: Vec::new(),
+            holds: Vec::new(),
+            closed: false,
+            overbooking: 0,
+            denied: Vec::new(),
+            last_hold_id: 0,
+            last_standby: 0,
+            last_sequence: 0,
+        }
+    }
+
+    /// Takes the last available seat
+    pub fn take_seat(&mut self) -> Option<String>{
+        let index = self.pick_seat(None)?;
+
+        self.take_seat_at(index)
+    }
+
+    /// Takes the last available seat and records its owner at the seat's fare
+    pub fn sell_seat(&mut self, owner: TicketOwner) -> Option<SoldTicket>{
+        let index = self.pick_seat(None)?;
+        let fare = self.fare_of(&self.seats[index]);
+
+        self.sell_seat_at(index, owner, fare, None)
+    }
+
+    /// Takes the cheapest available seat of the class and records its owner at the seat's fare
+    pub fn sell_seat_in(&mut self, class: FareClass, owner: TicketOwner) -> Option<SoldTicket>{
+        let index = self.pick_seat(Some(class))?;
+        let fare = self.fare_of(&self.seats[index]);
+
+        self.sell_seat_at(index, owner, fare, None)
+    }
+
+    /// Sets fare of the seats
+    pub fn set_fare(&mut self, seats: SeatRange, fare: Fare){
+        self.fares.push((seats, fare));
+    }
+
+    /// Returns `true` if the seat code is a seat of the flight, free, held or sold
+    pub fn has_seat(&self, seat: &str) -> bool{
+        self.seats.iter().any(|s| s == seat)
+            || self.holds.iter().any(|h| h.seat == seat)
+            || self.sold.iter().any(|t| !t.standby && t.seat == seat)
+    }
+
+    /// Returns fare of the seat, economy for free if no fare is set
+    pub fn fare_of(&self, seat: &str) -> Fare{
+        self.fares
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(seat))
+            .map_or(Fare::default(), |(_, fare)| *fare)
+    }
+
+    /// Returns sum of prices of sold tickets
+    pub fn revenue(&self) -> u64{
+        self.sold.iter().map(|t| t.fare.price).sum()
+    }
+
+    /// Returns sold ticket with the booking reference
+    pub fn ticket_by_ref(&self, booking_ref: &str) -> Option<&SoldTicket>{
+        self.sold.iter().find(|t| t.booking_ref == booking_ref)
+    }
+
+    /// Returns number of checked-in tickets
+    pub fn checked_in_count(&self) -> usize{
+        self.sold.iter().filter(|t| t.check_in.is_some()).count()
+    }
+
+    /// Returns `SaleClosed` if the flight is closed
+    pub(crate) fn check_open(&self) -> std::result::Result<(), Rejection>{
+        if self.closed { Err(Rejection::SaleClosed) } else { Ok(()) }
+    }
+
+    /// Returns number of boarded and checked-in passengers
+    pub fn boarding_count(&self) -> BoardingCount{
+        BoardingCount{ 
+            boarded: self.sold.iter().filter(|t| t.boarded_at.is_some()).count(),
+            checked_in: self.checked_in_count(),
+        }
+    }
+
+    /// Returns passengers of the flight sorted by seat
+    pub fn manifest(&self) -> Manifest{
+        let mut passengers: Vec<ManifestEntry> = self.sold
+            .iter()
+            .map(|t| ManifestEntry{ 
+                seat: t.seat.clone(), 
+                booking_ref: t.booking_ref.clone(), 
+                passenger_id: t.owner.passenger_id.clone(), 
+                ip: t.owner.ip, 
+                status: t.status(),
+            })
+            .collect();
+
+        passengers.sort_by(|a, b| a.seat.cmp(&b.seat));
+
+        Manifest{ flight_num: self.info.num, departure: self.info.departure, closed: self.closed, passengers }
+    }
+
+    /// Boards the checked-in passenger of the scanned ticket
+    pub(crate) fn board(&mut self, claims: &TicketClaims, now: SystemTime) -> ScanResult{
+        if self.closed { return ScanResult::Invalid; }
+
+        let ticket = self.sold
+            .iter_mut()
+            .find(|t| t.booking_ref == claims.booking_ref && t.revision == claims.revision 
+                && t.seat == claims.seat && t.owner.passenger_id == claims.passenger_id);
+
+        match ticket{
+            Some(ticket) if ticket.standby => ScanResult::Invalid,
+            Some(ticket) if ticket.boarded_at.is_some() => ScanResult::AlreadyBoarded,
+            Some(ticket) if ticket.check_in.is_some() => {
+                ticket.boarded_at = Some(now);
+                ScanResult::Accept
+            },
+            _ => ScanResult::Invalid,
+        }
+    }
+
+    /// Returns tickets of the owner
+    pub fn tickets_owned_by<'a>(&'a self, owner: &'a TicketOwner) -> impl Iterator<Item = &'a SoldTicket>{
+        self.sold.iter().filter(move |t| t.owner.same_as(owner))
+    }
+
+    /// Gives the ticket of the owner to another passenger
+    /// 
+    /// The recipient is not tied to the sender's address, see `TicketOwner::by_id`.
+    /// Check-in is cancelled, the new owner checks in again.
+    /// Ticket gets a new secret, the old one no longer proves ownership
+    pub(crate) fn transfer(&mut self, booking_ref: &str, owner: &TicketOwner, secret: u64, to: TicketOwner, now: SystemTime) -> std::result::Result<SoldTicket, Rejection>{
+        self.check_open()?;
+
+        let index = self.owned_ticket(booking_ref, owner, secret)?;
+        let ticket = &mut self.sold[index];
+
+        if ticket.boarded_at.is_some() { return Err(Rejection::AlreadyBoarded); }
+
+        let from = std::mem::replace(&mut ticket.owner, to.clone());
+        ticket.transfers.push(TicketTransfer{ from, to, at: now });
+        ticket.check_in = None;
+        ticket.revision+=1;
+        ticket.secret = new_ticket_secret();
+
+        Ok(ticket.clone())
+    }
+
+    /// Returns number of standby tickets waiting for a seat
+    pub fn standby_count(&self) -> usize{
+        self.sold.iter().filter(|t| t.standby).count()
+    }
+
+    /// Returns seat a standby ticket of the class is priced by, 
+    /// `None` if overbooking allowance is used up, seats are still held or the class has no sold seats
+    pub(crate) fn standby_fare_seat(&self, class: Option<FareClass>) -> Option<String>{
+        if !self.standby_open() { return None; }
+
+        self.sold
+            .iter()
+            .filter(|t| !t.standby && class.is_none_or(|c| t.fare.class == c))
+            .map(|t| &t.seat)
+            .min_by_key(|s| self.fare_of(s).price)
+            .cloned()
+    }
+
+    /// Sells a standby ticket beyond capacity if overbooking allowance is not used up
+    pub(crate) fn sell_standby(&mut self, owner: TicketOwner, fare: Fare, promo_code: Option<String>) -> Option<SoldTicket>{
+        if !self.standby_open() { return None; }
+
+        self.last_standby+=1;
+        self.record_sale(standby_seat(self.last_standby), owner, fare, promo_code);
+
+        let ticket = self.sold.last_mut().unwrap();
+        ticket.standby = true;
+
+        Some(ticket.clone())
+    }
+
+    /// Whether standby tickets are sold, live holds count toward capacity as they may still be bought
+    fn standby_open(&self) -> bool{
+        self.holds.is_empty() && self.standby_count() < self.overbooking as usize
+    }
+
+    /// Cancels the ticket of the owner
+    /// 
+    /// Freed seat goes to the earliest standby ticket or back to sale
+    pub(crate) fn cancel(&mut self, booking_ref: &str, owner: &TicketOwner, secret: u64) -> std::result::Result<SoldTicket, Rejection>{
+        self.check_open()?;
+
+        let index = self.owned_ticket(booking_ref, owner, secret)?;
+
+        if self.sold[index].boarded_at.is_some() { return Err(Rejection::AlreadyBoarded); }
+
+        let ticket = self.sold.remove(index);
+
+        if !ticket.standby {
+            self.free_seat(ticket.seat.clone());
+        }
+
+        Ok(ticket)
+    }
+
+    /// Gives free seats to standby tickets in order of sale and bumps the rest
+    pub(crate) fn resolve_overbooking(&mut self, now: SystemTime) -> OverbookingResolution{
+        self.release_expired(now);
+
+        let mut resolution = OverbookingResolution::default();
+
+        while self.standby_count() > 0 {
+            let Some(seat) = self.seats.pop() else { break; };
+            self.info.seats_num-=1;
+
+            resolution.reassigned.push(self.assign_standby(seat));
+        }
+
+        let (bumped, sold) = std::mem::take(&mut self.sold)
+            .into_iter()
+            .partition(|t| t.standby);
+
+        self.sold = sold;
+        self.denied.extend_from_slice(&bumped);
+        resolution.bumped = bumped;
+
+        resolution
+    }
+
+    /// Gives the seat to the earliest standby ticket, or returns it to sale
+    fn free_seat(&mut self, seat: String){
+        if self.standby_count() > 0 {
+            self.assign_standby(seat);
+        } else {
+            self.seats.push(seat);
+            self.info.seats_num+=1;
+        }
+    }
+
+    /// Moves the earliest standby ticket to the seat, there has to be one
+    /// 
+    /// Revision is bumped, so tickets signed for the standby seat no longer board.
+    /// The owner picks up the re-signed ticket with `Passenger::fetch_tickets` and checks in again
+    fn assign_standby(&mut self, seat: String) -> SoldTicket{
+        let ticket = self.sold.iter_mut().find(|t| t.standby).unwrap();
+
+        ticket.seat = seat;
+        ticket.standby = false;
+        ticket.check_in = None;
+        ticket.revision+=1;
+
+        ticket.clone()
+    }
+
+    /// Moves the ticket of the owner to a free seat of the same fare class
+    /// 
+    /// The new seat may not be priced above the previous one, the paid fare is kept.
+    /// Previous seat returns to sale, check-in is cancelled and the passenger checks in again
+    pub(crate) fn change_seat(&mut self, booking_ref: &str, owner: &TicketOwner, secret: u64, seat: &str, now: SystemTime) -> std::result::Result<SoldTicket, Rejection>{
+        self.check_open()?;
+        self.release_expired(now);
+
+        if self.lottery.as_ref().is_some_and(|l| l.is_pending()) {
+            return Err(Rejection::LotteryOnly);
+        }
+
+        let index = self.owned_ticket(booking_ref, owner, secret)?;
+
+        if self.sold[index].boarded_at.is_some() { return Err(Rejection::AlreadyBoarded); }
+        if self.sold[index].standby { return Err(Rejection::SeatUnavailable); }
+
+        let (fare, current) = (self.fare_of(seat), self.fare_of(&self.sold[index].seat));
+        let free = self.seats.iter().position(|s| s == seat)
+            .filter(|_| fare.class == self.sold[index].fare.class && fare.price <= current.price)
+            .ok_or(Rejection::SeatUnavailable)?;
+
+        self.seats[free] = std::mem::replace(&mut self.sold[index].seat, seat.to_string());
+        self.sold[index].check_in = None;
+        self.sold[index].revision+=1;
+
+        Ok(self.sold[index].clone())
+    }
+
+    /// Checks in the ticket of the owner, repeated check-in returns the first one
+    /// 
+    /// Standby tickets have no seat to check in for yet.
+    /// Sequence numbers are never reused, even after a check-in is cancelled
+    pub(crate) fn check_in(&mut self, booking_ref: &str, owner: &TicketOwner, secret: u64, now: SystemTime) -> std::result::Result<SoldTicket, Rejection>{
+        let index = self.owned_ticket(booking_ref, owner, secret)?;
+
+        if self.sold[index].standby { return Err(Rejection::SeatUnavailable); }
+
+        let ticket = &mut self.sold[index];
+
+        if ticket.check_in.is_none() {
+            self.last_sequence+=1;
+            ticket.check_in = Some(CheckIn{ group: CheckIn::group_for(ticket.fare.class), sequence: self.last_sequence, checked_in_at: now });
+        }
+
+        Ok(ticket.clone())
+    }
+
+    /// Returns index of the owner's ticket, `secret` proves the owner was given it
+    pub(crate) fn owned_ticket(&self, booking_ref: &str, owner: &TicketOwner, secret: u64) -> std::result::Result<usize, Rejection>{
+        self.sold
+            .iter()
+            .position(|t| t.booking_ref == booking_ref && t.secret == secret && t.owner.same_as(owner))
+            .ok_or(Rejection::TicketNotFound)
+    }
+
+    /// Returns seats of expired holds back to sale
+    pub fn release_expired(&mut self, now: SystemTime){
+        let (expired, holds) = std::mem::take(&mut self.holds)
+            .into_iter()
+            .partition(|h| h.expires <= now);
+
+        self.holds = holds;
+
+        for hold in expired.into_iter(){
+            self.seats.push(hold.seat);
+            self.info.seats_num+=1;
+        }
+    }
+
+    /// Returns index of the last available seat, 
+    /// or of the cheapest one if class is given
+    pub(crate) fn pick_seat(&self, class: Option<FareClass>) -> Option<usize>{
+        if self.info.seats_num == 0 { return None; }
+
+        let Some(class) = class else { return self.seats.len().checked_sub(1); };
+
+        self.seats
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (i, self.fare_of(s)))
+            .filter(|(_, f)| f.class == class)
+            .min_by_key(|(i, f)| (f.price, std::cmp::Reverse(*i)))
+            .map(|(i, _)| i)
+    }
+
+    /// Records the seat at `index` as sold for the fare
+    pub(crate) fn sell_seat_at(&mut self, index: usize, owner: TicketOwner, fare: Fare, promo_code: Option<String>) -> Option<SoldTicket>{
+        let seat = self.take_seat_at(index)?;
+
+        Some(self.record_sale(seat, owner, fare, promo_code))
+    }
+
+    /// Takes the seat at `index` out of sale until `expires`
+    pub(crate) fn hold_seat_at(&mut self, index: usize, owner: TicketOwner, fare: Fare, expires: SystemTime) -> Option<SeatHold>{
+        let seat = self.take_seat_at(index)?;
+
+        self.last_hold_id+=1;
+        let hold = SeatHold{ id: self.last_hold_id, seat, owner, fare, expires };
+        self.holds.push(hold.clone());
+
+        Some(hold)
+    }
+
+    /// Removes the hold given to the owner, expired holds are released before
+    pub(crate) fn take_hold(&mut self, id: u64, owner: &TicketOwner, now: SystemTime) -> std::result::Result<SeatHold, Rejection>{
+        self.release_expired(now);
+
+        let index = self.holds
+            .iter()
+            .position(|h| h.id == id && h.owner.same_as(owner))
+            .ok_or(Rejection::HoldExpired)?;
+
+        Ok(self.holds.remove(index))
+    }
+
+    /// Returns the hold back, used when it could not be bought
+    pub(crate) fn restore_hold(&mut self, hold: SeatHold){
+        self.holds.push(hold);
+    }
+
+    /// Records the held seat as sold for the held fare
+    pub(crate) fn sell_held(&mut self, hold: SeatHold, promo_code: Option<String>) -> SoldTicket{
+        self.record_sale(hold.seat, hold.owner, hold.fare, promo_code)
+    }
+
+    fn take_seat_at(&mut self, index: usize) -> Option<String>{
+        if self.info.seats_num == 0 || index >= self.seats.len() { return None; }
+
+        let ticket = self.seats.remove(index);
+        self.info.seats_num-=1;
+
+        Some(ticket)
+    }
+
+    /// Records the seat at `index` as sold with the given booking reference
+    /// 
+    /// Reference has to be unused on the flight
+    pub(crate) fn sell_seat_with_ref(&mut self, index: usize, owner: TicketOwner, fare: Fare, booking_ref: String) -> Option<SoldTicket>{
+        let seat = self.take_seat_at(index)?;
+
+        Some(self.record_sale_as(seat, owner, fare, None, booking_ref))
+    }
+
+    fn record_sale(&mut self, seat: String, owner: TicketOwner, fare: Fare, promo_code: Option<String>) -> SoldTicket{
+        let booking_ref = loop {
+            let booking_ref = new_booking_ref();
+            if self.ticket_by_ref(&booking_ref).is_none() { break booking_ref; }
+        };
+
+        self.record_sale_as(seat, owner, fare, promo_code, booking_ref)
+    }
+
+    fn record_sale_as(&mut self, seat: String, owner: TicketOwner, fare: Fare, promo_code: Option<String>, booking_ref: String) -> SoldTicket{
+        let ticket = SoldTicket{ 
+            seat, 
+            owner, 
+            fare, 
+            sold_at: SystemTime::now(), 
+            promo_code, 
+            booking_ref, 
+            check_in: None,
+            boarded_at: None,
+            transfers: Vec::new(),
+            standby: false,
+            revision: 0,
+            secret: new_ticket_secret(),
+        };
+
+        self.sold.push(ticket.clone());
+
+        ticket
+    }
+
+    /// Returns number of open holds counted for the quota key
+    pub(crate) fn holds_of(&self, key: &str) -> u32{
+        self.holds.iter()
+            .filter(|h| h.owner.keys().iter().any(|k| k == key))
+            .count() as u32
+    }
+
+    /// Returns number of sold and held tickets counted for the quota key
+    pub(crate) fn tickets_of(&self, key: &str) -> u32{
+        let owners = self.sold.iter().map(|t| &t.owner)
+            .chain(self.holds.iter().map(|h| &h.owner));
+
+        owners
+            .filter(|o| o.keys().iter().any(|k| k == key))
+            .count() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn owner(id: &str) -> TicketOwner{
+        TicketOwner{ passenger_id: Some(id.to_string()), ip: IpAddr::from([127, 0, 0, 1]) }
+    }
+
+    #[test]
+    fn check_in_sequence_is_not_reused(){
+        let mut flight = FlightDB::new(1, vec!["A1".to_string(), "B1".to_string(), "C1".to_string()]);
+        let now = SystemTime::UNIX_EPOCH;
+
+        let first = flight.sell_seat(owner("a")).unwrap();
+        let second = flight.sell_seat(owner("b")).unwrap();
+        let sequence = |t: SoldTicket| t.check_in.unwrap().sequence;
+
+        let mut sequences = vec![
+            sequence(flight.check_in(&first.booking_ref, &owner("a"), first.secret, now).unwrap()),
+            sequence(flight.check_in(&second.booking_ref, &owner("b"), second.secret, now).unwrap()),
+        ];
+
+        flight.change_seat(&first.booking_ref, &owner("a"), first.secret, "A1", now).unwrap();
+        sequences.push(sequence(flight.check_in(&first.booking_ref, &owner("a"), first.secret, now).unwrap()));
+
+        flight.cancel(&second.booking_ref, &owner("b"), second.secret).unwrap();
+        let third = flight.sell_seat(owner("c")).unwrap();
+        sequences.push(sequence(flight.check_in(&third.booking_ref, &owner("c"), third.secret, now).unwrap()));
+
+        assert_eq!(sequences, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn transferred_ticket_leaves_sender_address(){
+        let mut flight = FlightDB::new(1, vec!["A1".to_string()]);
+        let sold = flight.sell_seat(owner("a")).unwrap();
+
+        let given = flight.transfer(&sold.booking_ref, &owner("a"), sold.secret, TicketOwner::by_id("b".to_string()), SystemTime::UNIX_EPOCH).unwrap();
+        assert_eq!(given.owner.keys(), vec!["id:b".to_string()]);
+        assert_eq!((flight.tickets_of("ip:127.0.0.1"), flight.tickets_of("id:b")), (0, 1));
+
+        let from_elsewhere = TicketOwner{ passenger_id: Some("b".to_string()), ip: IpAddr::from([10, 0, 0, 2]) };
+        assert!(flight.owned_ticket(&given.booking_ref, &from_elsewhere, given.secret).is_ok());
+        assert!(flight.manifest().passengers[0].ip.is_unspecified());
+    }
+
+    #[test]
+    fn standby_ticket_does_not_check_in(){
+        let mut flight = FlightDB::new(1, vec!["A1".to_string()]);
+        flight.overbooking = 1;
+
+        let seated = flight.sell_seat(owner("a")).unwrap();
+        let standby = flight.sell_standby(owner("b"), Fare::default(), None).unwrap();
+        let now = SystemTime::UNIX_EPOCH;
+
+        assert_eq!(flight.check_in(&standby.booking_ref, &owner("b"), standby.secret, now).unwrap_err(), Rejection::SeatUnavailable);
+
+        flight.cancel(&seated.booking_ref, &owner("a"), seated.secret).unwrap();
+        let assigned = flight.check_in(&standby.booking_ref, &owner("b"), standby.secret, now).unwrap();
+        assert_eq!((assigned.seat.as_str(), assigned.standby), ("A1", false));
+    }
+}
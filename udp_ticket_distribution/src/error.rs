@@ -0,0 +1,206 @@
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, SystemTime};
+use super::*;
+use super::msg::*;
+
+/// Limit which was reached by `Rejection::QuotaExceeded`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuotaScope{
+    /// Tickets of one flight
+    Flight,
+    /// Tickets of all flights
+    Overall,
+}
+
+/// Reason why distributor refused to process a request
+///
+/// Sent in the payload of a response with the matching `PacketResponse`.
+/// `Passenger` returns it inside `std::io::Error`,
+/// use `Rejection::from_io` to get it back
+/// # Example
+/// ```rust
+/// # use udp_ticket_distribution::Rejection;
+/// # use std::time::Duration;
+/// let error: std::io::Error = Rejection::RateLimited{ retry_after: Duration::from_secs(1) }.into();
+///
+/// match Rejection::from_io(&error){
+///     Some(Rejection::RateLimited{ retry_after }) => assert_eq!(*retry_after, Duration::from_secs(1)),
+///     _ => unreachable!(),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rejection{
+    /// Too many requests, the next one can be sent after `retry_after`
+    RateLimited{ retry_after: Duration },
+    /// Passenger or address already has `limit` tickets
+    QuotaExceeded{ scope: QuotaScope, limit: u32 },
+    /// Flight seats are given by lottery, use `EnterLottery`
+    LotteryOnly,
+    /// Flight has no lottery accepting entries
+    LotteryClosed,
+    /// Passenger has to wait in the waiting room of the flight,
+    /// `position` is `None` without a valid queue token
+    NotAdmitted{ position: Option<u32> },
+    /// Sales of the flight start at `opens`
+    SaleNotOpen{ opens: SystemTime },
+    /// Sales of the flight are over
+    SaleClosed,
+    /// Hold is expired or was not given to the passenger
+    HoldExpired,
+    /// Promo code was not accepted
+    PromoInvalid{ issue: PromoIssue },
+    /// Passenger has no ticket with the booking reference
+    TicketNotFound,
+    /// Check-in for the flight starts at `opens`
+    CheckInNotOpen{ opens: SystemTime },
+    /// Check-in for the flight is over
+    CheckInClosed,
+    /// Ticket was already used for boarding
+    AlreadyBoarded,
+    /// Chosen seat is taken, of another fare class or priced higher, 
+    /// or a standby ticket has no seat yet
+    SeatUnavailable,
+    /// Sender is not an authorised gate, see `Distributor::allow_gate`
+    GateNotAllowed,
+}
+
+impl Rejection{
+    /// Returns `Rejection` if the error was made from it
+    pub fn from_io(error: &Error) -> Option<&Rejection>{
+        error.get_ref()?.downcast_ref::<Rejection>()
+    }
+
+    /// Returns response code used for this rejection
+    pub fn response(&self) -> PacketResponse{
+        match self{
+            Rejection::RateLimited{..} => PacketResponse::RateLimited,
+            Rejection::QuotaExceeded{..} => PacketResponse::QuotaExceeded,
+            Rejection::LotteryOnly => PacketResponse::LotteryOnly,
+            Rejection::LotteryClosed => PacketResponse::LotteryClosed,
+            Rejection::NotAdmitted{..} => PacketResponse::NotAdmitted,
+            Rejection::SaleNotOpen{..} => PacketResponse::SaleNotOpen,
+            Rejection::SaleClosed => PacketResponse::SaleClosed,
+            Rejection::HoldExpired => PacketResponse::HoldExpired,
+            Rejection::PromoInvalid{..} => PacketResponse::PromoInvalid,
+            Rejection::TicketNotFound => PacketResponse::TicketNotFound,
+            Rejection::CheckInNotOpen{..} => PacketResponse::CheckInNotOpen,
+            Rejection::CheckInClosed => PacketResponse::CheckInClosed,
+            Rejection::AlreadyBoarded => PacketResponse::AlreadyBoarded,
+            Rejection::SeatUnavailable => PacketResponse::SeatUnavailable,
+            Rejection::GateNotAllowed => PacketResponse::GateNotAllowed,
+        }
+    }
+
+    /// Returns `Rejection` sent in the packet, `None` if packet is not a rejection
+    ///
+    /// `RateLimited` is also read from the `RetryAfter` payload sent before rejections had one
+    pub(crate) fn from_packet(packet: &UdpPacket) -> Option<Rejection>{
+        if packet.response() == PacketResponse::RateLimited {
+            if let Some(RetryAfter{ retry_after_ms }) = decode::<RetryAfter>(packet) {
+                return Some(Rejection::RateLimited{ retry_after: Duration::from_millis(retry_after_ms) });
+            }
+        }
+        decode::<Rejection>(packet).filter(|r| r.response() == packet.response())
+    }
+
+    /// Returns response packet with this rejection
+    ///
+    /// `RateLimited` keeps its `RetryAfter` payload so older passengers can read it
+    pub(crate) fn into_packet(self, request: PacketRequest) -> UdpPacket{
+        let data = match &self{
+            Rejection::RateLimited{ retry_after } => encode(&RetryAfter{
+                retry_after_ms: retry_after.as_millis().try_into().unwrap_or(u64::MAX),
+            }),
+            _ => encode(&self),
+        };
+
+        UdpPacket::new_with_request(request)
+            .set_response(self.response())
+            .set_data(&data)
+    }
+
+    fn kind(&self) -> ErrorKind{
+        match self{
+            Rejection::RateLimited{..} => ErrorKind::WouldBlock,
+            Rejection::QuotaExceeded{..} => ErrorKind::PermissionDenied,
+            Rejection::LotteryOnly => ErrorKind::PermissionDenied,
+            Rejection::LotteryClosed => ErrorKind::PermissionDenied,
+            Rejection::NotAdmitted{..} => ErrorKind::WouldBlock,
+            Rejection::SaleNotOpen{..} => ErrorKind::WouldBlock,
+            Rejection::SaleClosed => ErrorKind::PermissionDenied,
+            Rejection::HoldExpired => ErrorKind::TimedOut,
+            Rejection::PromoInvalid{..} => ErrorKind::InvalidInput,
+            Rejection::TicketNotFound => ErrorKind::NotFound,
+            Rejection::CheckInNotOpen{..} => ErrorKind::WouldBlock,
+            Rejection::CheckInClosed => ErrorKind::PermissionDenied,
+            Rejection::AlreadyBoarded => ErrorKind::PermissionDenied,
+            Rejection::SeatUnavailable => ErrorKind::InvalidInput,
+            Rejection::GateNotAllowed => ErrorKind::PermissionDenied,
+        }
+    }
+}
+
+impl fmt::Display for Rejection{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            Rejection::RateLimited{ retry_after } =>
+                write!(f, "Too many requests, retry after {} ms", retry_after.as_millis()),
+            Rejection::QuotaExceeded{ scope: QuotaScope::Flight, limit } =>
+                write!(f, "Limit of {} tickets per flight is reached", limit),
+            Rejection::QuotaExceeded{ scope: QuotaScope::Overall, limit } =>
+                write!(f, "Limit of {} tickets is reached", limit),
+            Rejection::LotteryOnly => write!(f, "Seats of the flight are given by lottery"),
+            Rejection::LotteryClosed => write!(f, "Flight has no lottery open for entries"),
+            Rejection::NotAdmitted{ position: Some(position) } =>
+                write!(f, "Not admitted yet, position in the queue is {}", position),
+            Rejection::NotAdmitted{ position: None } => write!(f, "Join the queue of the flight first"),
+            Rejection::SaleNotOpen{ opens } => match opens.duration_since(SystemTime::now()){
+                Ok(left) => write!(f, "Sales are not open yet, they open in {} s", left.as_secs()),
+                Err(_) => write!(f, "Sales are not open yet"),
+            },
+            Rejection::SaleClosed => write!(f, "Sales of the flight are closed"),
+            Rejection::HoldExpired => write!(f, "Hold is expired or unknown"),
+            Rejection::PromoInvalid{ issue } => write!(f, "Promo code is not accepted: {:?}", issue),
+            Rejection::TicketNotFound => write!(f, "Ticket not found"),
+            Rejection::CheckInNotOpen{ opens } => match opens.duration_since(SystemTime::now()){
+                Ok(left) => write!(f, "Check-in is not open yet, it opens in {} s", left.as_secs()),
+                Err(_) => write!(f, "Check-in is not open yet"),
+            },
+            Rejection::CheckInClosed => write!(f, "Check-in for the flight is closed"),
+            Rejection::AlreadyBoarded => write!(f, "Ticket was already used for boarding"),
+            Rejection::SeatUnavailable => write!(f, "Seat is not available for the ticket"),
+            Rejection::GateNotAllowed => write!(f, "Address is not an authorised gate"),
+        }
+    }
+}
+
+impl std::error::Error for Rejection{}
+
+impl From<Rejection> for Error{
+    fn from(rejection: Rejection) -> Self{
+        Error::new(rejection.kind(), rejection)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn rate_limited_keeps_retry_after_payload(){
+        let legacy = UdpPacket::new_with_request(PacketRequest::RequestTicket)
+            .set_response(PacketResponse::RateLimited)
+            .set_data(&encode(&RetryAfter{ retry_after_ms: 1500 }));
+        assert_eq!(Rejection::from_packet(&legacy), Some(Rejection::RateLimited{ retry_after: Duration::from_millis(1500) }));
+
+        let packet = Rejection::RateLimited{ retry_after: Duration::from_millis(250) }.into_packet(PacketRequest::RequestTicket);
+        assert_eq!(decode::<RetryAfter>(&packet).map(|r| r.retry_after_ms), Some(250));
+        assert_eq!(Rejection::from_packet(&packet), Some(Rejection::RateLimited{ retry_after: Duration::from_millis(250) }));
+
+        let enum_payload = UdpPacket::new_with_request(PacketRequest::RequestTicket)
+            .set_response(PacketResponse::RateLimited)
+            .set_data(&encode(&Rejection::RateLimited{ retry_after: Duration::from_secs(2) }));
+        assert_eq!(Rejection::from_packet(&enum_payload), Some(Rejection::RateLimited{ retry_after: Duration::from_secs(2) }));
+    }
+}
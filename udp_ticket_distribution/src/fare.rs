@@ -0,0 +1,63 @@
+use std::ops::RangeInclusive;
+use super::*;
+
+/// Fare class of a seat
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum FareClass{
+    /// Default class of seats without a fare
+    #[default]
+    Economy,
+    /// Premium economy
+    Premium,
+    /// Business class, boards first
+    Business,
+}
+
+/// Class and price of a seat
+/// 
+/// Price is kept in minor currency units (cents)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fare{
+    /// Class of the seat
+    pub class: FareClass,
+    /// Price in cents
+    pub price: u64,
+}
+
+/// Seats a fare is set for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SeatRange{
+    /// Every seat of the rows, e.g. `Rows(1..=3)` is "A1" to "F3"
+    Rows(RangeInclusive<u8>),
+    /// One seat, e.g. `Seat("C7".to_string())`
+    Seat(String),
+}
+
+impl SeatRange{
+    /// Returns `true` if the seat code is in the range
+    pub fn contains(&self, seat: &str) -> bool{
+        match self{
+            SeatRange::Rows(rows) => seat_row(seat).is_some_and(|row| rows.contains(&row)),
+            SeatRange::Seat(code) => code == seat,
+        }
+    }
+}
+
+/// Returns row of the seat code, "A12" is in the row 12
+fn seat_row(seat: &str) -> Option<u8>{
+    seat.trim_start_matches(|c: char| c.is_ascii_alphabetic()).parse().ok()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn seat_range_contains(){
+        let rows = SeatRange::Rows(2..=4);
+
+        assert!(rows.contains("A2") && rows.contains("F4"));
+        assert!(!rows.contains("A1") && !rows.contains("B12") && !rows.contains("bad"));
+        assert!(SeatRange::Seat("C7".to_string()).contains("C7"));
+    }
+}
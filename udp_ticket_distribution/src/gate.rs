@@ -0,0 +1,61 @@
+use std::io::{Error, ErrorKind};
+use super::*;
+use super::msg::*;
+use super::pass::response_error;
+
+/// Implements gate agent's role in boarding a flight
+/// 
+/// Gate sends scanned boarding pass or ticket codes to the distributor,
+/// see `BoardingPass::to_bytes` and `SignedTicket::to_bytes`
+pub struct Gate{
+    udp_client: ClientSide,
+    flight_num: u32,
+}
+
+impl Gate{
+    /// Returns `Result<Gate>` boarding the flight if socket binding was successful
+    /// 
+    /// Distributor has to allow the gate's address, see `Distributor::allow_gate`
+    pub async fn new(flight_num: u32) -> Result<Self>{
+        Ok(Gate { 
+            udp_client: ClientSide::new_with_address(Auto).await?, 
+            flight_num,
+        })
+    }
+
+    /// Returns `Result<Gate>` boarding the flight with socket bound to `addr`
+    pub async fn new_with_address(flight_num: u32, addr: String) -> Result<Self>{
+        Ok(Gate { 
+            udp_client: ClientSide::new_with_address(Manual(addr)).await?, 
+            flight_num,
+        })
+    }
+
+    /// Returns number of the boarded flight
+    pub fn flight_num(&self) -> u32{ self.flight_num }
+
+    /// Attemp to ping specified distributor address
+    pub async fn try_connect(&mut self, serv_addr: &String) -> Result<()>{
+        self.udp_client.set_server(Manual( serv_addr.to_owned() )).await
+    }
+
+    /// Boards the passenger of the scanned code
+    /// 
+    /// Rejected codes are reported in `GateScan::result`, not as errors.
+    /// Fails with `Rejection::GateNotAllowed` if the gate's address is not allowed
+    pub async fn scan(&mut self, code: &[u8]) -> Result<GateScan>{
+        let request = ScanRequest{ flight_num: self.flight_num, code: code.to_vec() };
+
+        let packet = 
+            UdpPacket::new_with_request(PacketRequest::GateScan)
+                .set_data(&encode(&request));
+
+        let packet = self.udp_client.send_and_recv(packet).await?;
+
+        match packet.response() {
+            PacketResponse::Ok => decode::<GateScan>(&packet)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Response is invalid")),
+            _ => Err(response_error(&packet)),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::time::SystemTime;
+use super::*;
+
+/// Number of holds one passenger or address may have open on a flight
+pub(crate) const MAX_OPEN_HOLDS: u32 = 2;
+
+/// Seat reserved for an owner at a quoted fare until `expires`
+#[derive(Debug, Clone)]
+pub struct SeatHold{
+    /// Hold number, unique within the flight
+    pub id: u64,
+    /// Seat code, e.g. "A1"
+    pub seat: String,
+    /// Who the seat is held for
+    pub owner: TicketOwner,
+    /// Fare the owner will pay
+    pub fare: Fare,
+    /// Seat returns to sale after this time
+    pub expires: SystemTime,
+}
+
+/// Hold as seen by the passenger
+/// 
+/// Pass it to `Passenger::buy_held` before `expires` to pay the quoted fare
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TicketHold{
+    pub flight_num: u32,
+    pub id: u64,
+    pub seat: String,
+    pub fare: Fare,
+    pub expires: SystemTime,
+}
+
+impl SeatHold{
+    /// Returns passenger's view of the hold
+    pub fn ticket_hold(&self, flight_num: u32) -> TicketHold{
+        TicketHold { 
+            flight_num, 
+            id: self.id, 
+            seat: self.seat.clone(), 
+            fare: self.fare, 
+            expires: self.expires,
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use super::*;
+
+/// Flight of an itinerary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItineraryLeg{
+    pub flight_num: u32,
+    /// Fare class of the seat, `None` takes any seat
+    #[serde(default)]
+    pub class: Option<FareClass>,
+}
+
+impl ItineraryLeg{
+    /// Returns leg with any seat of the flight
+    pub fn new(flight_num: u32) -> Self{
+        ItineraryLeg { flight_num, class: None }
+    }
+}
+
+/// Tickets of every leg booked together
+#[derive(Debug, Clone)]
+pub struct Itinerary{
+    /// Booking reference of every leg's ticket
+    pub booking_ref: String,
+    /// Tickets in order of the requested legs
+    pub legs: Vec<FlightTicket>,
+}
@@ -1,26 +1,94 @@
 //! `Passenger` and `Distributor` implementation using `udp_tools` crate
 
-pub use pass::Passenger;
-pub use distr::Distributor;
+pub use pass::{Passenger, FlightTicket};
+pub use distr::{Distributor, DistributorSummary, FlightDB, SoldTicket, TicketOwner, TicketTransfer};
+pub use storage::FlightStorage;
+pub use router::{PassengerRouter, ShardMap, ShardConfig};
+pub use limits::RateLimit;
+pub use quota::QuotaRules;
+pub use error::{Rejection, QuotaScope};
+pub use lottery::{Lottery, LotteryDraw, LotteryStatus};
+pub use queue::{WaitingRoom, QueuePlace};
+pub use fare::{Fare, FareClass, SeatRange};
+pub use pricing::{PricingPolicy, PricingInput, InventoryPricing};
+pub use hold::{SeatHold, TicketHold};
+pub use promo::{PromoCode, Discount, PromoIssue};
+pub use ticket::{SignedTicket, TicketClaims, TicketVerifier};
+pub use boarding::{CheckInWindow, CheckIn, BoardingPass};
+pub use boarding::{ScanResult, BoardingCount, GateScan, BoardingStatus, ManifestEntry, Manifest, ManifestFormat};
+pub use gate::Gate;
+pub use overbook::OverbookingResolution;
+pub use itinerary::{Itinerary, ItineraryLeg};
 
 
 mod distr;
 mod pass;
+mod router;
+mod storage;
+mod limits;
+mod error;
+mod msg;
+mod logic;
+mod quota;
+mod lottery;
+mod queue;
+mod fare;
+mod pricing;
+mod hold;
+mod promo;
+mod ticket;
+mod boarding;
+mod gate;
+mod overbook;
+mod itinerary;
+mod rng;
 
 use serde::{Deserialize,Serialize};
 use std::io::Result;
+use std::time::{Duration, SystemTime};
 use AddressSelection::*;
 use udp_tools::*;
 
 
 /// Used as transmitted info in packets
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
 pub struct FlightInfo{
     /// Flight number
     pub num: u32,
     /// Number of seats. 
     /// Value can be up to 42*6 (42 rows with ABCDEF seats)
     pub seats_num: u8,
+    /// Tickets are sold from this time, `None` if sales are open since creation
+    #[serde(default)]
+    pub sale_opens: Option<SystemTime>,
+    /// Tickets are sold until this time, `None` if sales never close
+    #[serde(default)]
+    pub sale_closes: Option<SystemTime>,
+    /// Departure time, used by pricing policies
+    #[serde(default)]
+    pub departure: Option<SystemTime>,
+}
+
+impl FlightInfo{
+    /// Returns `true` if tickets are sold at `now`
+    pub fn is_on_sale(&self, now: SystemTime) -> bool{ self.check_sale(now).is_ok() }
+
+    /// Returns time left until sales open, `None` if they are already open
+    pub fn opens_in(&self, now: SystemTime) -> Option<Duration>{
+        self.sale_opens?.duration_since(now).ok()
+    }
+
+    /// Returns rejection for requests outside of the sales window
+    pub(crate) fn check_sale(&self, now: SystemTime) -> std::result::Result<(), Rejection>{
+        if let Some(opens) = self.sale_opens {
+            if now < opens { return Err(Rejection::SaleNotOpen{ opens }); }
+        }
+
+        match self.sale_closes{
+            Some(closes) if now >= closes => Err(Rejection::SaleClosed),
+            _ => Ok(()),
+        }
+    }
 }
 
 /// Converts vector of structs FlightInfo to vector of bytes.
@@ -34,7 +102,7 @@ pub struct FlightInfo{
 /// let mut data = Vec::new();
 /// 
 /// for i in 1..10{
-///     data.push(FlightInfo{ num: i, seats_num: 42*6});
+///     data.push(FlightInfo{ num: i, seats_num: 42*6, ..Default::default() });
 /// }
 /// 
 /// let serialized_data: Vec<u8> = convert_to_bytes(&data);
@@ -64,7 +132,7 @@ pub fn convert_to_bytes(data: &Vec<FlightInfo>) -> Vec<u8>{
 /// let mut data = Vec::new();
 /// 
 /// for i in 1..10{
-///     data.push(FlightInfo{ num: i, seats_num: 42*6});
+///     data.push(FlightInfo{ num: i, seats_num: 42*6, ..Default::default() });
 /// }
 /// 
 /// let serialized_data: Vec<u8> = convert_to_bytes(&data);
@@ -83,12 +151,56 @@ pub fn convert_to_flightinfo(data: &Vec<u8>) -> Vec<FlightInfo>{
 mod tests{
     use super::*;
 
+    /// Returns distributor on a free local port with flights of the given rows
+    async fn distributor(rows: &[u8]) -> std::io::Result<Distributor>{
+        let distr = Distributor::new_with_address("127.0.0.1:0".to_string()).await?;
+        for &rows in rows{
+            distr.gen_fake_flight(rows).await;
+        }
+
+        Ok(distr)
+    }
+
+    /// Returns passenger connected to the distributor
+    async fn passenger(distr: &Distributor, id: Option<&str>) -> std::io::Result<Passenger>{
+        connect(distr, Passenger::new().await?, id).await
+    }
+
+    /// Returns passenger sending from `addr`, connected to the distributor
+    async fn passenger_at(distr: &Distributor, addr: &str, id: Option<&str>) -> std::io::Result<Passenger>{
+        connect(distr, Passenger::new_with_address(addr.to_string()).await?, id).await
+    }
+
+    /// Sets ID of the passenger and connects it to the distributor
+    async fn connect(distr: &Distributor, mut psngr: Passenger, id: Option<&str>) -> std::io::Result<Passenger>{
+        if let Some(id) = id { psngr.set_id(id); }
+        psngr.try_connect(&distr.get_address()).await?;
+
+        Ok(psngr)
+    }
+
+    /// Returns gate of the flight connected to the distributor
+    async fn connected_gate(distr: &Distributor, flight_num: u32) -> std::io::Result<Gate>{
+        let mut gate = Gate::new(flight_num).await?;
+        gate.try_connect(&distr.get_address()).await?;
+
+        Ok(gate)
+    }
+
+    /// Returns gate of the flight sending from `addr`, connected to the distributor
+    async fn connected_gate_at(distr: &Distributor, flight_num: u32, addr: &str) -> std::io::Result<Gate>{
+        let mut gate = Gate::new_with_address(flight_num, addr.to_string()).await?;
+        gate.try_connect(&distr.get_address()).await?;
+
+        Ok(gate)
+    }
+
     #[test]
     fn udp_socket_distribution_converting_test(){
         let mut data = Vec::new();
 
         for i in 1..10{
-            data.push(FlightInfo{ num: i, seats_num: 42*6});
+            data.push(FlightInfo{ num: i, seats_num: 42*6, ..Default::default() });
         }
 
         let serialized_data = convert_to_bytes(&data);
@@ -105,7 +217,6 @@ mod tests{
 
     #[tokio::test]
     async fn udp_socket_distribution_communicating_test() -> std::io::Result<()>{
-        use super::distr::FlightDB;
         use std::sync::Arc;
         use futures::lock::Mutex;
 
@@ -115,13 +226,7 @@ mod tests{
 
         distr.gen_fake_flight(CLIENTS_NUM/6).await; //100/6=16 rows  4 seats
 
-        let arc = distr.db_storage();
-        let lock = arc.read().unwrap();
-
-        let mut flightdb = lock[0].clone();
-
-        drop(lock);
-        drop(arc);
+        let mut flightdb = distr.db_storage().snapshot()[0].clone();
 
         //clients side
         let distr_addr = distr.get_address();
@@ -143,16 +248,13 @@ mod tests{
 
         assert_eq!(info.len(), 1);
 
-        let mut psngers_flightdb = FlightDB{
-            info: info[0],
-            seats: Vec::new(),
-        };
+        let mut psngers_flightdb = FlightDB::new(info[0].num, Vec::new());
 
         let mut tasks = Vec::new();
 
-        for i in 0..CLIENTS_NUM.into(){
-            let client = psngers[i].clone();
-            let data_num = psngers_flightdb.info.num.clone();
+        for client in psngers.iter(){
+            let client = client.clone();
+            let data_num = psngers_flightdb.info.num;
             tasks.push(tokio::spawn(async move {
                 client.lock().await.
                     query_ticket_for_a_flight(data_num).await.unwrap()
@@ -180,12 +282,787 @@ mod tests{
 
         assert_eq!(flightdb.info.seats_num, psngers_flightdb.info.seats_num);
 
-        let mut iter = std::iter::zip(flightdb.seats, psngers_flightdb.seats).map( | (f,pf) |{
+        for (f, pf) in std::iter::zip(flightdb.seats, psngers_flightdb.seats){
             assert_eq!(f, pf);
-        });
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_shutdown_test() -> std::io::Result<()>{
+        let mut distr = distributor(&[1]).await?;
 
-        while let Some(_) = iter.next() {}
+        let mut psngr = passenger(&distr, None).await?;
+        psngr.query_ticket_for_a_flight(1).await?.unwrap();
+
+        let summary = distr.shutdown(Duration::from_secs(1)).await?;
+
+        assert!(summary.server.is_drained());
+        assert_eq!(summary.flights[0].info.seats_num, 5);
+        assert_eq!(summary.flights[0].sold.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_sharding_test() -> std::io::Result<()>{
+        const SHARDS_NUM: usize = 3;
+
+        let mut distrs = Vec::new();
+        for index in 0..SHARDS_NUM{
+            let mut distr = Distributor::new_with_address("127.0.0.1:0".to_string()).await?;
+            distr.set_shard(ShardConfig{ index, count: SHARDS_NUM, map: ShardMap::Hash })?;
+
+            distr.gen_fake_flight(1).await;
+            distr.gen_fake_flight(1).await;
+            distrs.push(distr);
+        }
+
+        let addrs: Vec<String> = distrs.iter().map(|d| d.get_address()).collect();
+        let mut router = PassengerRouter::connect(&addrs, ShardMap::Hash).await?;
+
+        let flights = router.fetch_flights().await?;
+        assert_eq!(flights.len(), SHARDS_NUM*2);
+        assert!(flights.windows(2).all(|w| w[0].num < w[1].num));
+
+        for flight in flights.iter(){
+            let shard = ShardMap::Hash.shard_for(flight.num, SHARDS_NUM);
+            assert!(distrs[shard].db_storage().flight(flight.num).is_some());
+
+            assert!(router.query_ticket_for_a_flight(flight.num).await?.is_some());
+        }
+
+        assert_eq!(router.acquired_tickets().len(), SHARDS_NUM*2);
+
+        // Shard which can own no flight is refused
+        let distr = &mut distrs[0];
+        assert!(distr.set_shard(ShardConfig{ index: SHARDS_NUM, count: SHARDS_NUM, map: ShardMap::Hash }).is_err());
+        assert!(distr.set_shard(ShardConfig{ index: 0, count: 0, map: ShardMap::Hash }).is_err());
+        assert!(distr.set_shard(ShardConfig{ index: 0, count: 1, map: ShardMap::Explicit([(1, 1)].into()) }).is_err());
+        assert!(router.fetch_flights().await?.iter().all(|f| f.seats_num == 5));
+
+        // Other flight requests go through the serving shard
+        let flight_num = flights[0].num;
+        router.set_id("passenger");
+        router.shard_for(flight_num)?.hold_ticket(flight_num, None).await?.unwrap();
+        let listed = router.fetch_flights().await?;
+        assert_eq!(listed.iter().find(|f| f.num == flight_num).unwrap().seats_num, 4);
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn udp_socket_distribution_rate_limit_test() -> std::io::Result<()>{
+        use std::time::Duration;
+
+        let distr = distributor(&[1]).await?;
+        distr.set_rate_limit(PacketRequest::RequestTicket, Some(RateLimit{ burst: 2, per_second: 10.0 }));
+
+        let mut psngr = passenger(&distr, Some("hoarder")).await?;
+
+        assert!(psngr.query_ticket_for_a_flight(1).await?.is_some());
+        assert!(psngr.query_ticket_for_a_flight(1).await?.is_some());
+
+        let err = psngr.query_ticket_for_a_flight(1).await.unwrap_err();
+        let Some(Rejection::RateLimited{ retry_after }) = Rejection::from_io(&err) else {
+            panic!("Unexpected error: {}", err);
+        };
+        assert!(*retry_after > Duration::ZERO && *retry_after <= Duration::from_millis(100));
+
+        // Other requests are not limited
+        assert_eq!(psngr.fetch_flights().await?[0].seats_num, 4);
+
+        // Same passenger from another address shares the bucket
+        let mut same_id = passenger(&distr, Some("hoarder")).await?;
+        distr.set_rate_limit(PacketRequest::RequestTicket, Some(RateLimit{ burst: 1, per_second: 10.0 }));
+
+        assert!(same_id.query_ticket_for_a_flight(1).await?.is_some());
+        assert!(psngr.query_ticket_for_a_flight(1).await.is_err());
+
+        tokio::time::sleep(*retry_after + Duration::from_millis(100)).await;
+        assert!(psngr.query_ticket_for_a_flight(1).await?.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_legacy_request_test() -> std::io::Result<()>{
+        let distr = distributor(&[1]).await?;
+
+        // Client of the original protocol sends a bare flight number
+        let mut client = ClientSide::new_with_address(Manual("127.0.0.1:0".to_string())).await?;
+        client.set_server(Manual(distr.get_address())).await?;
+
+        let request = |flight_num: u32| UdpPacket::new_with_request(PacketRequest::RequestTicket)
+            .set_data(&flight_num.to_le_bytes());
+
+        let packet = client.send_and_recv(request(1)).await?;
+        assert_eq!(packet.response(), PacketResponse::Ok);
+        let seat = String::from_utf8(packet.try_retrieve_data().unwrap()).unwrap();
+        assert_eq!(distr.db_storage().snapshot()[0].sold[0].seat, seat);
+
+        assert_eq!(client.send_and_recv(request(9)).await?.response(), PacketResponse::TicketsSold);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_quota_test() -> std::io::Result<()>{
+        let distr = distributor(&[2, 2]).await?;
+        distr.set_quota(QuotaRules{ per_flight: Some(2), overall: None });
+
+        let mut psngr = passenger(&distr, Some("first")).await?;
+
+        assert!(psngr.query_ticket_for_a_flight(1).await?.is_some());
+        assert!(psngr.query_ticket_for_a_flight(1).await?.is_some());
+
+        let err = psngr.query_ticket_for_a_flight(1).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::QuotaExceeded{ scope: QuotaScope::Flight, limit: 2 }));
+
+        assert!(psngr.query_ticket_for_a_flight(2).await?.is_some());
+
+        // Quotas are changed at runtime, earlier tickets are counted
+        distr.set_quota(QuotaRules{ per_flight: None, overall: Some(3) });
+
+        let err = psngr.query_ticket_for_a_flight(2).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::QuotaExceeded{ scope: QuotaScope::Overall, limit: 3 }));
+
+        distr.set_quota(QuotaRules::default());
+        assert!(psngr.query_ticket_for_a_flight(2).await?.is_some());
+
+        let flight = distr.db_storage().snapshot()[0].clone();
+        assert_eq!(flight.sold.len(), 2);
+        assert!(flight.sold.iter().all(|t| t.owner.passenger_id.as_deref() == Some("first")));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_lottery_test() -> std::io::Result<()>{
+        use std::time::Duration;
+
+        let distr = distributor(&[1]).await?;
+        // Two of six seats are left for the lottery
+        for _ in 0..4 { distr.db_storage().take_seat(1); }
+        distr.start_lottery(1, Duration::from_millis(300))?;
+
+        let mut psngrs = Vec::new();
+        for (n, id) in ["a", "b", "c", "d"].into_iter().enumerate(){
+            let mut psngr = passenger_at(&distr, &format!("127.0.0.{}:0", n + 2), Some(id)).await?;
+            psngr.enter_lottery(1).await?;
+            psngrs.push(psngr);
+        }
+
+        // Repeated entry is ignored
+        psngrs[0].enter_lottery(1).await?;
+        assert_eq!(psngrs[0].lottery_status(1).await?, LotteryStatus::Pending);
+
+        // Another ID from an address which already entered is ignored too
+        let mut alias = passenger_at(&distr, "127.0.0.2:0", Some("a2")).await?;
+        alias.enter_lottery(1).await?;
+        assert_eq!(alias.lottery_status(1).await?, LotteryStatus::NotEntered);
+
+        // "d" already reached overall quota on another flight, the draw skips it
+        distr.gen_fake_flight(1).await;
+        psngrs[3].query_ticket_for_a_flight(2).await?;
+        distr.set_quota(QuotaRules{ per_flight: None, overall: Some(1) });
+
+        let err = psngrs[0].query_ticket_for_a_flight(1).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::LotteryOnly));
+
+        // Draw is refused until entries are closed
+        assert!(distr.draw_lottery(1, Some(7)).is_err());
+        tokio::time::sleep(Duration::from_millis(400)).await;
+
+        let err = psngrs[0].enter_lottery(1).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::LotteryClosed));
+
+        let draw = distr.draw_lottery(1, Some(7))?;
+        assert_eq!(draw.seed, 7);
+        assert_eq!(draw.entries_num, 4);
+        assert_eq!(draw.winners.len(), 2);
+        assert!(draw.winners.iter().all(|w| w.owner.passenger_id.as_deref() != Some("d")));
+
+        let mut won = 0;
+        for psngr in psngrs.iter_mut(){
+            match psngr.lottery_status(1).await?{
+                LotteryStatus::Won{ .. } => won += 1,
+                LotteryStatus::Lost => {},
+                status => panic!("Unexpected status {:?}", status),
+            }
+        }
+        assert_eq!(won, 2);
+        let won_tickets = psngrs.iter().flat_map(|p| p.acquired_tickets()).filter(|t| t.flight_num == 1).count();
+        assert_eq!(won_tickets, 2);
+
+        // Same seed and entries give the same winners
+        let entries = distr.db_storage().snapshot()[0].lottery.as_ref().unwrap().entries.clone();
+        assert!(draw.verify(&entries));
+        let order = LotteryDraw::order(7, &entries);
+        let drawn = order.iter().filter(|o| !draw.skipped.contains(o));
+        assert!(draw.winners.iter().zip(drawn).all(|(w, o)| &w.owner == o));
+
+        let mut forged = draw.clone();
+        forged.winners.reverse();
+        assert!(!forged.verify(&entries));
+
+        assert!(distr.draw_lottery(1, Some(7)).is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_waiting_room_test() -> std::io::Result<()>{
+        use std::time::Duration;
+
+        let distr = distributor(&[1]).await?;
+        distr.open_waiting_room(1, RateLimit{ burst: 1, per_second: 0.0 })?;
+
+        let mut first = passenger(&distr, Some("first")).await?;
+
+        let mut second = passenger(&distr, Some("second")).await?;
+
+        // Ticket is not given without a place in the queue
+        let err = first.query_ticket_for_a_flight(1).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::NotAdmitted{ position: None }));
+
+        let place = first.join_queue(1).await?;
+        assert!(place.is_admitted());
+        assert_eq!(first.join_queue(1).await?, place);
+
+        assert_eq!(second.join_queue(1).await?.position, 1);
+
+        let err = second.query_ticket_for_a_flight(1).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::NotAdmitted{ position: Some(1) }));
+
+        assert!(first.query_ticket_for_a_flight(1).await?.is_some());
+
+        // Admission is used up by the purchase
+        let err = first.query_ticket_for_a_flight(1).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::NotAdmitted{ position: None }));
+        assert_ne!(first.join_queue(1).await?, place);
+
+        // Admission rate is changed at runtime
+        distr.set_admission_rate(1, RateLimit{ burst: 1, per_second: 20.0 })?;
+        second.wait_for_admission(1, Duration::from_millis(20)).await?;
+        assert!(second.queue_position(1).await?.is_admitted());
+        assert!(second.query_ticket_for_a_flight(1).await?.is_some());
+
+        assert_eq!(distr.close_waiting_room(1)?.len(), 3);
+
+        let mut third = passenger(&distr, None).await?;
+        assert!(third.query_ticket_for_a_flight(1).await?.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_sale_window_test() -> std::io::Result<()>{
+        let distr = distributor(&[1]).await?;
+
+        let now = SystemTime::now();
+        let opens = now + Duration::from_millis(300);
+        distr.set_sale_window(1, Some(opens), Some(opens + Duration::from_millis(300)))?;
+        assert!(distr.set_sale_window(1, Some(opens), Some(now)).is_err());
+
+        let mut psngr = passenger(&distr, None).await?;
+
+        let info = psngr.fetch_flights().await?[0];
+        assert_eq!(info.sale_opens, Some(opens));
+        assert!(info.opens_in(SystemTime::now()).is_some());
+
+        let err = psngr.query_ticket_for_a_flight(1).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::SaleNotOpen{ opens }));
+
+        // Lottery and waiting room don't let passengers in before the sale either
+        let err = psngr.join_queue(1).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::SaleNotOpen{ opens }));
+        distr.start_lottery(1, Duration::from_secs(60))?;
+        let err = psngr.enter_lottery(1).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::SaleNotOpen{ opens }));
+        distr.db_storage().flight(1).unwrap().lock().unwrap().lottery = None;
+
+        tokio::time::sleep(info.opens_in(SystemTime::now()).unwrap()).await;
+        assert!(psngr.query_ticket_for_a_flight(1).await?.is_some());
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let err = psngr.query_ticket_for_a_flight(1).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::SaleClosed));
+
+        // Many flights with sale windows still fit in one response
+        for _ in 0..100 { distr.gen_fake_flight(1).await; }
+        assert_eq!(psngr.fetch_flights().await?.len(), 101);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_fare_test() -> std::io::Result<()>{
+        let distr = distributor(&[3]).await?;
+
+        let business = Fare{ class: FareClass::Business, price: 50000 };
+        distr.set_fare(1, SeatRange::Rows(1..=1), business)?;
+        distr.set_fare(1, SeatRange::Seat("C1".to_string()), Fare{ price: 40000, ..business })?;
+        let err = distr.set_fare(1, SeatRange::Seat("BUSINESS".to_string()), business).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        distr.set_fare(1, SeatRange::Rows(2..=3), Fare{ class: FareClass::Economy, price: 10000 })?;
+
+        let mut psngr = passenger(&distr, None).await?;
+
+        // The cheapest seat of the class is given first
+        let ticket = psngr.query_ticket_in_class(1, FareClass::Business).await?.unwrap();
+        assert_eq!((ticket.ticket.as_str(), ticket.fare.price), ("C1", 40000));
+
+        let ticket = psngr.query_ticket_in_class(1, FareClass::Business).await?.unwrap();
+        assert_eq!(ticket.fare, business);
+
+        assert!(psngr.query_ticket_in_class(1, FareClass::Premium).await?.is_none());
+
+        assert!(psngr.query_ticket_for_a_flight(1).await?.is_some());
+        assert_eq!(psngr.acquired_tickets()[2].fare.price, 10000);
+
+        let flight = distr.db_storage().snapshot()[0].clone();
+        assert_eq!(flight.revenue(), 100000);
+        assert_eq!(flight.info.seats_num, 15);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_pricing_test() -> std::io::Result<()>{
+        let distr = distributor(&[1]).await?;
+        distr.set_fare(1, SeatRange::Rows(1..=1), Fare{ class: FareClass::Economy, price: 1000 })?;
+        distr.set_pricing(InventoryPricing{ max_markup: 2.0, velocity_markup: 0.0 });
+        distr.set_hold_time(Duration::from_millis(200));
+
+        let mut psngr = passenger(&distr, None).await?;
+
+        // Price is quoted while 6 of 6 seats are left
+        let hold = psngr.hold_ticket(1, None).await?.unwrap();
+        assert_eq!(hold.fare.price, 1000);
+        assert_eq!(psngr.fetch_flights().await?[0].seats_num, 5);
+
+        // Later sales are priced higher
+        let ticket = psngr.query_ticket_in_class(1, FareClass::Economy).await?.unwrap();
+        assert_eq!(ticket.fare.price, 1167);
+
+        // Held seat is paid at the quoted price
+        assert_eq!(psngr.buy_held(&hold).await?.fare.price, 1000);
+        let err = psngr.buy_held(&hold).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::HoldExpired));
+
+        // Expired hold returns the seat to sale
+        let hold = psngr.hold_ticket(1, None).await?.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let err = psngr.buy_held(&hold).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::HoldExpired));
+        assert_eq!(psngr.fetch_flights().await?[0].seats_num, 4);
+
+        let flight = distr.db_storage().snapshot()[0].clone();
+        assert_eq!(flight.revenue(), 2167);
+        assert!(flight.holds.is_empty());
+
+        // Open holds are capped per owner
+        psngr.hold_ticket(1, None).await?.unwrap();
+        psngr.hold_ticket(1, None).await?.unwrap();
+        let err = psngr.hold_ticket(1, None).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::QuotaExceeded{ scope: QuotaScope::Flight, limit: 2 }));
+
+        // Flights list returns expired holds to sale
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(distr.db_storage().snapshot()[0].holds.len(), 2);
+        assert_eq!(psngr.fetch_flights().await?[0].seats_num, 4);
+        assert!(distr.db_storage().snapshot()[0].holds.is_empty());
+
+        // Open holds count toward the overall quota
+        distr.set_quota(QuotaRules{ per_flight: None, overall: Some(3) });
+        psngr.hold_ticket(1, None).await?.unwrap();
+        let err = psngr.hold_ticket(1, None).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::QuotaExceeded{ scope: QuotaScope::Overall, limit: 3 }));
+
+        // Ticket request returns expired holds to sale
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(psngr.query_ticket_for_a_flight(1).await?.is_some());
+        assert!(distr.db_storage().snapshot()[0].holds.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_promo_test() -> std::io::Result<()>{
+        let distr = distributor(&[1]).await?;
+        distr.set_fare(1, SeatRange::Rows(1..=1), Fare{ class: FareClass::Economy, price: 1000 })?;
+
+        distr.add_promo("HALF", PromoCode{ max_uses: Some(2), flights: Some(vec![1]), ..PromoCode::new(Discount::Percent(50)) });
+
+        let mut psngr = passenger(&distr, None).await?;
+
+        let ticket = psngr.query_ticket_with_promo(1, None, "HALF").await?.unwrap();
+        assert_eq!(ticket.fare.price, 500);
+
+        let hold = psngr.hold_ticket(1, None).await?.unwrap();
+        assert_eq!(hold.fare.price, 1000);
+        assert_eq!(psngr.buy_held_with_promo(&hold, "HALF").await?.fare.price, 500);
+
+        let err = psngr.query_ticket_with_promo(1, None, "HALF").await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::PromoInvalid{ issue: PromoIssue::UsedUp }));
+
+        // Rejected code leaves the seat unsold
+        let flight = distr.db_storage().snapshot()[0].clone();
+        assert_eq!(flight.info.seats_num, 4);
+        assert_eq!(flight.revenue(), 1000);
+        assert!(flight.sold.iter().all(|t| t.promo_code.as_deref() == Some("HALF")));
+
+        assert_eq!(distr.promo_usage("HALF"), Some(2));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_signed_ticket_test() -> std::io::Result<()>{
+        let distr = distributor(&[1]).await?;
+        distr.set_signing_key(&[7; 32]);
+
+        let verifier_key = distr.public_key();
+        let verifier = TicketVerifier::new(&verifier_key)?;
+
+        let mut psngr = passenger(&distr, Some("passenger")).await?;
+        psngr.query_ticket_for_a_flight(1).await?;
+
+        let ticket = psngr.acquired_tickets()[0].clone();
+        let signed = ticket.signed.clone().unwrap();
+
+        let claims = verifier.verify_bytes(&signed.to_bytes())?;
+        assert_eq!(claims.flight_num, 1);
+        assert_eq!(claims.seat, ticket.ticket);
+        assert_eq!(claims.passenger_id.as_deref(), Some("passenger"));
+        assert_eq!(claims.booking_ref, ticket.booking_ref);
+        assert_eq!(claims.booking_ref.len(), 6);
+
+        // Ticket signed again is the same
+        assert_eq!(distr.sign_ticket(1, &ticket.booking_ref)?, signed);
+
+        // Damaged signature is rejected
+        let mut forged = signed.to_bytes();
+        *forged.last_mut().unwrap() ^= 1;
+        assert!(verifier.verify_bytes(&forged).is_err());
+
+        // Ticket of another key is rejected
+        distr.set_signing_key(&[8; 32]);
+        let other = distr.sign_ticket(1, &ticket.booking_ref)?;
+        assert!(verifier.verify(&other).is_err());
+        assert_eq!(distr.public_keys()[1], verifier_key);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_check_in_test() -> std::io::Result<()>{
+        let distr = distributor(&[2]).await?;
+        distr.set_fare(1, SeatRange::Rows(1..=1), Fare{ class: FareClass::Business, price: 5000 })?;
+
+        let hour = Duration::from_secs(3600);
+        distr.set_departure(1, Some(SystemTime::now() + 2*hour))?;
+
+        let mut first = passenger(&distr, Some("first")).await?;
+        let economy = first.query_ticket_in_class(1, FareClass::Economy).await?.unwrap();
+
+        let mut second = passenger(&distr, Some("second")).await?;
+        let business = second.query_ticket_in_class(1, FareClass::Business).await?.unwrap();
+
+        // Only the owner checks in the ticket
+        let err = second.check_in(&economy).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::TicketNotFound));
+
+        let pass = first.check_in(&economy).await?;
+        assert_eq!((pass.seat.as_str(), pass.check_in.group, pass.check_in.sequence), (economy.ticket.as_str(), 3, 1));
+        assert_eq!(pass.passenger_id.as_deref(), Some("first"));
+        assert_eq!(first.check_in(&economy).await?, pass);
+
+        let pass = second.check_in(&business).await?;
+        assert_eq!((pass.check_in.group, pass.check_in.sequence), (1, 2));
+
+        let verifier = TicketVerifier::new(&distr.public_key())?;
+        assert_eq!(verifier.verify(&pass.ticket)?.booking_ref, business.booking_ref);
+        assert_eq!(distr.checked_in_count(1)?, 2);
+
+        distr.set_departure(1, Some(SystemTime::now() + hour/4))?;
+        let err = first.check_in(&economy).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::CheckInClosed));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_gate_scan_test() -> std::io::Result<()>{
+        let distr = distributor(&[1, 1]).await?;
+
+        let mut psngr = passenger_at(&distr, "127.0.0.2:0", Some("passenger")).await?;
+        psngr.query_ticket_for_a_flight(1).await?;
+        psngr.query_ticket_for_a_flight(1).await?;
+
+        let tickets = psngr.acquired_tickets();
+        let pass = psngr.check_in(&tickets[0]).await?;
+
+        let mut gate = connected_gate_at(&distr, 1, "127.0.0.1:0").await?;
+
+        // Scans are accepted only from allowed gates
+        let err = gate.scan(&pass.to_bytes()).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::GateNotAllowed));
+        distr.allow_gate("127.0.0.1".parse().unwrap());
+
+        // Passenger can't board itself from its own address
+        let mut rogue = connected_gate_at(&distr, 1, "127.0.0.2:0").await?;
+        let err = rogue.scan(&pass.to_bytes()).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::GateNotAllowed));
+        assert_eq!(distr.boarding_count(1)?.boarded, 0);
+
+        let scan = gate.scan(&pass.to_bytes()).await?;
+        assert_eq!(scan.result, ScanResult::Accept);
+        assert_eq!(scan.seat, Some(pass.seat.clone()));
+        assert_eq!(scan.count, BoardingCount{ boarded: 1, checked_in: 1 });
+
+        assert_eq!(gate.scan(&pass.ticket.to_bytes()).await?.result, ScanResult::AlreadyBoarded);
+
+        // Ticket without check-in does not board
+        let unchecked = tickets[1].signed.clone().unwrap();
+        assert_eq!(gate.scan(&unchecked.to_bytes()).await?.result, ScanResult::Invalid);
+        assert_eq!(gate.scan(b"not a ticket").await?.result, ScanResult::Invalid);
+
+        let mut other_gate = connected_gate(&distr, 2).await?;
+        assert_eq!(other_gate.scan(&pass.to_bytes()).await?.result, ScanResult::WrongFlight);
+
+        let manifest = distr.close_flight(1)?;
+        assert!(manifest.closed);
+        assert_eq!(manifest.passengers.len(), 2);
+        assert_eq!(manifest.count(BoardingStatus::Boarded), 1);
+        assert_eq!(manifest.count(BoardingStatus::Sold), 1);
+
+        // Closed flight neither boards nor sells
+        let pass = psngr.check_in(&tickets[0]).await;
+        assert_eq!(Rejection::from_io(&pass.unwrap_err()), Some(&Rejection::CheckInClosed));
+        assert_eq!(gate.scan(&unchecked.to_bytes()).await?.result, ScanResult::Invalid);
+        let err = psngr.query_ticket_for_a_flight(1).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::SaleClosed));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_transfer_test() -> std::io::Result<()>{
+        let distr = distributor(&[1]).await?;
+
+        let mut alice = passenger(&distr, Some("alice")).await?;
+        alice.query_ticket_for_a_flight(1).await?;
+        alice.query_ticket_for_a_flight(1).await?;
+
+        // Recipient uses the ticket from an address of their own
+        let mut bob = passenger_at(&distr, "127.0.0.2:0", Some("bob")).await?;
+
+        let ticket = alice.acquired_tickets()[0].clone();
+        let pass = alice.check_in(&ticket).await?;
+
+        // Passenger claiming alice's ID can't see, cancel or give away her tickets
+        let mut eve = passenger(&distr, Some("alice")).await?;
+        assert!(eve.fetch_tickets().await?.is_empty());
+        let stolen = FlightTicket{ secret: None, ..ticket.clone() };
+        let err = eve.cancel_ticket(&stolen).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::TicketNotFound));
+        let err = eve.transfer_ticket(&stolen, "eve").await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::TicketNotFound));
+
+        let given = alice.transfer_ticket(&ticket, "bob").await?;
+        assert_eq!(alice.acquired_tickets().len(), 1);
+        assert_ne!(given.secret, ticket.secret);
+
+        let err = alice.transfer_ticket(&ticket, "bob").await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::TicketNotFound));
+
+        let err = bob.claim_ticket(1, &given.booking_ref, given.secret.unwrap() ^ 1).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::TicketNotFound));
+        bob.claim_ticket(1, &given.booking_ref, given.secret.unwrap()).await?;
+
+        let tickets = bob.fetch_tickets().await?;
+        assert_eq!(tickets.len(), 1);
+        assert_eq!(tickets[0].booking_ref, ticket.booking_ref);
+
+        let verifier = TicketVerifier::new(&distr.public_key())?;
+        let claims = verifier.verify(tickets[0].signed.as_ref().unwrap())?;
+        assert_eq!(claims.passenger_id.as_deref(), Some("bob"));
+
+        // Pass of the previous owner does not board
+        distr.allow_gate("127.0.0.1".parse().unwrap());
+        let mut gate = connected_gate(&distr, 1).await?;
+        assert_eq!(gate.scan(&pass.to_bytes()).await?.result, ScanResult::Invalid);
+
+        let sold = distr.db_storage().snapshot()[0].ticket_by_ref(&ticket.booking_ref).unwrap().clone();
+        assert_eq!(sold.transfers.len(), 1);
+        assert_eq!(sold.transfers[0].from.passenger_id.as_deref(), Some("alice"));
+        assert_eq!(sold.check_in, None);
+        assert_eq!((sold.revision, claims.revision), (1, 1));
+
+        // New owner is counted in quotas
+        distr.set_quota(QuotaRules{ per_flight: Some(1), overall: None });
+        let err = alice.transfer_ticket(&alice.acquired_tickets()[0], "bob").await.unwrap_err();
+        assert!(matches!(Rejection::from_io(&err), Some(Rejection::QuotaExceeded{ .. })));
+        assert_eq!(alice.fetch_tickets().await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_change_seat_test() -> std::io::Result<()>{
+        let distr = distributor(&[2]).await?;
+        distr.set_fare(1, SeatRange::Rows(1..=1), Fare{ class: FareClass::Business, price: 5000 })?;
+        distr.set_departure(1, Some(SystemTime::now() + Duration::from_secs(3600)))?;
+        distr.allow_gate("127.0.0.1".parse().unwrap());
+
+        let mut psngr = passenger(&distr, Some("passenger")).await?;
+        let ticket = psngr.query_ticket_in_class(1, FareClass::Economy).await?.unwrap();
+        let old_pass = psngr.check_in(&ticket).await?;
+
+        let flight = distr.db_storage().snapshot()[0].clone();
+        let mut economy = flight.seats.iter().filter(|s| flight.fare_of(s).class == FareClass::Economy);
+        let free = economy.next().unwrap().clone();
+        let pricey = economy.next().unwrap().clone();
+        let business = flight.seats.iter().find(|s| flight.fare_of(s).class == FareClass::Business).unwrap().clone();
+
+        // Seat priced above the paid one is refused
+        distr.set_fare(1, SeatRange::Seat(pricey.clone()), Fare{ class: FareClass::Economy, price: 1000 })?;
+        let err = psngr.change_seat(&ticket, pricey.as_str()).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::SeatUnavailable));
+
+        let changed = psngr.change_seat(&ticket, free.as_str()).await?;
+        assert_eq!(changed.ticket, free);
+        assert_eq!(changed.booking_ref, ticket.booking_ref);
+        assert_eq!(psngr.acquired_tickets()[0].ticket, free);
+
+        let flight = distr.db_storage().snapshot()[0].clone();
+        assert!(flight.seats.contains(&ticket.ticket));
+        assert!(!flight.seats.contains(&free));
+        assert_eq!(flight.info.seats_num as usize, flight.seats.len());
+
+        // Taken seats and seats of another class are refused
+        let unavailable = Some(&Rejection::SeatUnavailable);
+        let err = psngr.change_seat(&changed, free.as_str()).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), unavailable);
+        let err = psngr.change_seat(&changed, business.as_str()).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), unavailable);
+
+        let verifier = TicketVerifier::new(&distr.public_key())?;
+        assert_eq!(verifier.verify(changed.signed.as_ref().unwrap())?.seat, free);
+
+        // Pass of the old seat does not board, the new check-in does
+        let mut gate = connected_gate(&distr, 1).await?;
+        assert_eq!(gate.scan(&old_pass.to_bytes()).await?.result, ScanResult::Invalid);
+
+        let pass = psngr.check_in(&changed).await?;
+        assert_eq!(pass.seat, free);
+        let scan = gate.scan(&pass.to_bytes()).await?;
+        assert_eq!((scan.result, scan.seat), (ScanResult::Accept, Some(free)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_overbooking_test() -> std::io::Result<()>{
+        let distr = distributor(&[1]).await?;
+        assert_eq!(distr.set_overbooking(1, 34)?, 2);
+        distr.set_departure(1, Some(SystemTime::now() + Duration::from_secs(3600)))?;
+        distr.allow_gate("127.0.0.1".parse().unwrap());
+
+        let mut psngr = passenger(&distr, Some("passenger")).await?;
+
+        for _ in 0..6 {
+            psngr.query_ticket_for_a_flight(1).await?.unwrap();
+        }
+
+        let standby = psngr.query_ticket_for_a_flight(1).await?.unwrap();
+        assert_eq!(standby, "SB1");
+        let standby = psngr.acquired_tickets().last().unwrap().clone();
+        let err = psngr.check_in(&standby).await.unwrap_err();
+        assert_eq!(Rejection::from_io(&err), Some(&Rejection::SeatUnavailable));
+
+        // Cancelled seat goes to the standby ticket
+        let cancelled = psngr.acquired_tickets()[0].clone();
+        psngr.cancel_ticket(&cancelled).await?;
+        let tickets = psngr.fetch_tickets().await?;
+        assert_eq!(tickets.len(), 6);
+        let assigned = tickets.iter().find(|t| t.booking_ref == standby.booking_ref).unwrap().clone();
+        assert_eq!(assigned.ticket, cancelled.ticket);
+
+        // Re-signed ticket checks in and boards
+        let mut gate = connected_gate(&distr, 1).await?;
+
+        let pass = psngr.check_in(&assigned).await?;
+        assert_eq!(pass.seat, cancelled.ticket);
+        assert_eq!(gate.scan(&pass.to_bytes()).await?.result, ScanResult::Accept);
+
+        assert!(psngr.query_ticket_for_a_flight(1).await?.is_some());
+        assert!(psngr.query_ticket_for_a_flight(1).await?.is_some());
+        assert_eq!(psngr.query_ticket_for_a_flight(1).await?, None);
+
+        let resolution = distr.resolve_overbooking(1)?;
+        assert!(resolution.reassigned.is_empty());
+        assert_eq!(resolution.bumped.len(), 2);
+
+        let flight = distr.db_storage().snapshot()[0].clone();
+        assert_eq!((flight.sold.len(), flight.denied.len(), flight.standby_count()), (6, 2, 0));
+        assert_eq!(psngr.fetch_tickets().await?.len(), 6);
+
+        // Standby is not sold while a seat is held
+        distr.gen_fake_flight(1).await;
+        distr.set_overbooking(2, 34)?;
+        distr.set_hold_time(Duration::from_millis(200));
+        psngr.hold_ticket(2, None).await?.unwrap();
+        for _ in 0..5 {
+            psngr.query_ticket_for_a_flight(2).await?.unwrap();
+        }
+        assert_eq!(psngr.query_ticket_for_a_flight(2).await?, None);
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        let seat = psngr.query_ticket_for_a_flight(2).await?.unwrap();
+        assert!(!seat.starts_with("SB"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn udp_socket_distribution_itinerary_test() -> std::io::Result<()>{
+        let distr = distributor(&[1, 1, 1]).await?;
+
+        let mut psngr = passenger(&distr, Some("passenger")).await?;
+
+        for _ in 0..6 {
+            psngr.query_ticket_for_a_flight(3).await?.unwrap();
+        }
+
+        let itinerary = psngr.book_itinerary(&[ItineraryLeg::new(2), ItineraryLeg::new(1)]).await?.unwrap();
+        assert_eq!(itinerary.legs.iter().map(|t| t.flight_num).collect::<Vec<_>>(), vec![2, 1]);
+        assert!(itinerary.legs.iter().all(|t| t.booking_ref == itinerary.booking_ref));
+        assert_eq!(psngr.acquired_tickets().len(), 8);
+
+        // Sold out leg books nothing
+        assert!(psngr.book_itinerary(&[ItineraryLeg::new(1), ItineraryLeg::new(3)]).await?.is_none());
+        assert!(psngr.book_itinerary(&[ItineraryLeg::new(1), ItineraryLeg::new(9)]).await.is_err());
+        assert!(psngr.book_itinerary(&[ItineraryLeg::new(1), ItineraryLeg::new(1)]).await.is_err());
+
+        let flights = distr.db_storage().snapshot();
+        assert_eq!((flights[0].sold.len(), flights[1].sold.len()), (1, 1));
+        assert_eq!(psngr.acquired_tickets().len(), 8);
+
+        // Expired hold frees the last seat of a leg
+        distr.set_hold_time(Duration::from_millis(200));
+        for _ in 0..4 {
+            psngr.query_ticket_for_a_flight(1).await?.unwrap();
+        }
+        psngr.hold_ticket(1, None).await?.unwrap();
+        assert!(psngr.book_itinerary(&[ItineraryLeg::new(1), ItineraryLeg::new(2)]).await?.is_none());
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert!(psngr.book_itinerary(&[ItineraryLeg::new(1), ItineraryLeg::new(2)]).await?.is_some());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use super::*;
+use super::msg::*;
+
+/// Token bucket settings of a request type
+///
+/// Client may send `burst` requests at once,
+/// then one request per `1 / per_second` seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit{
+    /// Bucket capacity
+    pub burst: u32,
+    /// Tokens added to the bucket every second
+    pub per_second: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket{
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket{
+    fn refill(&mut self, limit: &RateLimit, now: Instant){
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed*limit.per_second).min(limit.burst as f64);
+        self.updated = now;
+    }
+
+    /// Returns time to wait for the next token
+    fn wait_time(&self, limit: &RateLimit) -> Duration{
+        if limit.per_second <= 0.0 { return Duration::MAX; }
+
+        Duration::from_secs_f64( ((1.0 - self.tokens) / limit.per_second).max(0.0) )
+    }
+}
+
+/// Buckets are kept per request type and per client key
+type BucketKey = (PacketRequest, String);
+
+/// Token bucket rate limiter keyed by source IP and passenger ID
+///
+/// Clones share the same buckets and settings
+#[derive(Clone, Default)]
+pub(crate) struct RateLimiter{
+    limits: Arc<Mutex< HashMap<PacketRequest, RateLimit> >>,
+    buckets: Arc<Mutex< HashMap<BucketKey, Bucket> >>,
+}
+
+impl RateLimiter{
+    /// Buckets map is cleaned from refilled buckets after this size
+    const PRUNE_AFTER: usize = 4096;
+
+    pub fn set_limit(&self, request: PacketRequest, limit: Option<RateLimit>){
+        let mut limits = self.limits.lock().unwrap();
+
+        match limit{
+            Some(limit) => { limits.insert(request, limit); },
+            None => { limits.remove(&request); },
+        }
+
+        self.buckets.lock().unwrap().retain(|(r, _), _| *r != request);
+    }
+
+    pub fn limit(&self, request: PacketRequest) -> Option<RateLimit>{
+        self.limits.lock().unwrap().get(&request).copied()
+    }
+
+    /// Takes a token from every client's bucket
+    ///
+    /// Returns time to wait if any of the buckets is empty,
+    /// in this case no tokens are taken
+    pub fn check(&self, request: PacketRequest, keys: &[String], now: Instant) -> std::result::Result<(), Duration>{
+        let Some(limit) = self.limit(request) else { return Ok(()); };
+
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() > Self::PRUNE_AFTER {
+            buckets.retain(|_, b| {
+                b.refill(&limit, now);
+                b.tokens < limit.burst as f64
+            });
+        }
+
+        let mut wait = Duration::ZERO;
+        for key in keys{
+            let bucket = buckets.entry((request, key.clone()))
+                .or_insert(Bucket{ tokens: limit.burst as f64, updated: now });
+
+            bucket.refill(&limit, now);
+
+            if bucket.tokens < 1.0 {
+                wait = wait.max(bucket.wait_time(&limit));
+            }
+        }
+
+        if !wait.is_zero() { return Err(wait); }
+
+        for key in keys{
+            if let Some(bucket) = buckets.get_mut(&(request, key.clone())) {
+                bucket.tokens -= 1.0;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Middleware for RateLimiter{
+    fn call(&self, packet: UdpPacket, ctx: RequestContext, next: Next) -> PacketFuture{
+        let request = packet.request();
+
+        if self.limit(request).is_none() {
+            return next.run(packet, ctx);
+        }
+
+        let keys = TicketOwner{
+            passenger_id: decode::<PassengerTag>(&packet).and_then(|t| t.passenger_id),
+            ip: ctx.peer_addr().ip(),
+        }.keys();
+
+        match self.check(request, &keys, ctx.received_instant()){
+            Ok(()) => next.run(packet, ctx),
+            Err(wait) => {
+                let retry_after = wait.max(Duration::from_millis(1));
+
+                Box::pin(std::future::ready(
+                    Rejection::RateLimited{ retry_after }.into_packet(request)
+                ))
+            },
+        }
+    }
+}
@@ -0,0 +1,639 @@
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use super::*;
+use super::msg::*;
+use super::quota::QuotaBook;
+use super::pricing::Pricing;
+use super::promo::PromoBook;
+use super::ticket::TicketSigner;
+use super::boarding::scanned_ticket;
+use super::ticket::new_booking_ref;
+use super::hold::MAX_OPEN_HOLDS;
+
+/// Distributor's processing of requests
+/// 
+/// Clones share the same storage and settings
+#[derive(Clone, Default)]
+pub(crate) struct Logic{
+    pub db_storage: Arc<FlightStorage>,
+    pub quotas: Arc<QuotaBook>,
+    pub pricing: Arc<Pricing>,
+    pub promos: Arc<PromoBook>,
+    pub signer: Arc<TicketSigner>,
+    pub check_in: Arc<Mutex<CheckInWindow>>,
+    pub gates: Arc<Mutex<HashSet<IpAddr>>>,
+}
+
+impl Logic{
+    pub fn process(&self, packet: UdpPacket, ctx: &RequestContext) -> UdpPacket{
+        match packet.request(){
+            PacketRequest::Ping => packet.set_response(PacketResponse::Ok),
+            PacketRequest::GetFlights => {
+                let flights = self.db_storage.flights_info(ctx.received_at());
+
+                packet
+                    .set_response(PacketResponse::Ok)
+                    .set_data(&convert_to_bytes(&flights))
+            },
+            PacketRequest::RequestTicket => {
+                if let Some(flight_num) = legacy_flight_num(&packet) {
+                    let request = TicketRequest{ flight_num, passenger_id: None, queue_token: None, class: None, promo_code: None };
+
+                    return match self.request_ticket(request, ctx){
+                        Ok(Some(ticket)) => packet
+                            .set_response(PacketResponse::Ok)
+                            .set_data(ticket.seat.as_bytes()),
+                        Ok(None) => packet
+                            .set_response(PacketResponse::TicketsSold),
+                        Err(rejection) => rejection.into_packet(packet.request()),
+                    };
+                }
+
+                let Some(request) = decode::<TicketRequest>(&packet) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+
+                let flight_num = request.flight_num;
+
+                match self.request_ticket(request, ctx){
+                    Ok(Some(ticket)) => packet
+                        .set_response(PacketResponse::Ok)
+                        .set_data(&encode(&self.issued(flight_num, ticket))),
+                    Ok(None) => packet
+                        .set_response(PacketResponse::TicketsSold),
+                    Err(rejection) => rejection.into_packet(packet.request()),
+                }
+            },
+            PacketRequest::EnterLottery => {
+                let Some(request) = decode::<FlightRequest>(&packet) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+                let Some(flight) = self.db_storage.flight(request.flight_num) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+
+                let owner = TicketOwner{ passenger_id: request.passenger_id, ip: ctx.peer_addr().ip() };
+
+                let mut flight = flight.lock().unwrap();
+                let entered = flight.info.check_sale(ctx.received_at()).and_then(|_| match &mut flight.lottery{
+                    Some(lottery) => lottery.enter(owner, ctx.received_at()),
+                    None => Err(Rejection::LotteryClosed),
+                });
+
+                match entered{
+                    Ok(()) => packet.set_response(PacketResponse::Ok),
+                    Err(rejection) => rejection.into_packet(packet.request()),
+                }
+            },
+            PacketRequest::LotteryResult => {
+                let Some(request) = decode::<FlightRequest>(&packet) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+                let Some(flight) = self.db_storage.flight(request.flight_num) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+
+                let owner = TicketOwner{ passenger_id: request.passenger_id, ip: ctx.peer_addr().ip() };
+
+                let flight = flight.lock().unwrap();
+
+                let mut status = match &flight.lottery{
+                    Some(lottery) => lottery.status(&owner),
+                    None => LotteryStatus::NotEntered,
+                };
+
+                // Ticket and its secret go only to the address the entry was made from
+                if let LotteryStatus::Won{ seat, ticket, secret, .. } = &mut status {
+                    if let Some(sold) = flight.sold.iter().find(|t| &t.seat == seat && t.owner == owner) {
+                        *ticket = Some(self.signer.sign(request.flight_num, sold));
+                        *secret = Some(sold.secret);
+                    }
+                }
+
+                packet
+                    .set_response(PacketResponse::Ok)
+                    .set_data(&encode(&status))
+            },
+            PacketRequest::JoinQueue => {
+                let Some(request) = decode::<FlightRequest>(&packet) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+                let Some(flight) = self.db_storage.flight(request.flight_num) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+
+                let owner = TicketOwner{ passenger_id: request.passenger_id, ip: ctx.peer_addr().ip() };
+
+                let mut flight = flight.lock().unwrap();
+                if let Err(rejection) = flight.info.check_sale(ctx.received_at()) {
+                    return rejection.into_packet(packet.request());
+                }
+
+                // Flight without a waiting room admits everybody
+                let place = match &mut flight.waiting_room{
+                    Some(room) => room.join(owner, ctx.received_instant()),
+                    None => QueuePlace{ token: 0, position: 0 },
+                };
+
+                packet
+                    .set_response(PacketResponse::Ok)
+                    .set_data(&encode(&place))
+            },
+            PacketRequest::QueuePosition => {
+                let Some(request) = decode::<QueueRequest>(&packet) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+                let Some(flight) = self.db_storage.flight(request.flight_num) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+
+                let owner = TicketOwner{ passenger_id: request.passenger_id, ip: ctx.peer_addr().ip() };
+
+                let place = match &mut flight.lock().unwrap().waiting_room{
+                    Some(room) => room.place(request.token, &owner, ctx.received_instant()),
+                    None => Some(QueuePlace{ token: request.token, position: 0 }),
+                };
+
+                match place{
+                    Some(place) => packet
+                        .set_response(PacketResponse::Ok)
+                        .set_data(&encode(&place)),
+                    None => Rejection::NotAdmitted{ position: None }.into_packet(packet.request()),
+                }
+            },
+            PacketRequest::HoldTicket => {
+                let Some(request) = decode::<TicketRequest>(&packet) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+                let flight_num = request.flight_num;
+
+                match self.hold_ticket(request, ctx){
+                    Ok(Some(hold)) => packet
+                        .set_response(PacketResponse::Ok)
+                        .set_data(&encode(&hold.ticket_hold(flight_num))),
+                    Ok(None) => packet
+                        .set_response(PacketResponse::TicketsSold),
+                    Err(rejection) => rejection.into_packet(packet.request()),
+                }
+            },
+            PacketRequest::BookItinerary => {
+                let Some(request) = decode::<ItineraryRequest>(&packet) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+
+                let mut flights: Vec<u32> = request.legs.iter().map(|l| l.leg.flight_num).collect();
+                flights.sort_unstable();
+                flights.dedup();
+                if flights.is_empty() || flights.len() != request.legs.len() 
+                    || flights.iter().any(|&num| self.db_storage.flight(num).is_none()) {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                }
+
+                match self.book_itinerary(request, ctx){
+                    Ok(Some(booked)) => packet
+                        .set_response(PacketResponse::Ok)
+                        .set_data(&encode(&booked)),
+                    Ok(None) => packet
+                        .set_response(PacketResponse::TicketsSold),
+                    Err(rejection) => rejection.into_packet(packet.request()),
+                }
+            },
+            PacketRequest::BuyHeld => {
+                let Some(request) = decode::<BuyRequest>(&packet) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+
+                let flight_num = request.flight_num;
+
+                match self.buy_held(request, ctx){
+                    Ok(ticket) => packet
+                        .set_response(PacketResponse::Ok)
+                        .set_data(&encode(&self.issued(flight_num, ticket))),
+                    Err(rejection) => rejection.into_packet(packet.request()),
+                }
+            },
+            PacketRequest::GateScan => {
+                if !self.gates.lock().unwrap().contains(&ctx.peer_addr().ip()) {
+                    return Rejection::GateNotAllowed.into_packet(packet.request());
+                }
+                let Some(request) = decode::<ScanRequest>(&packet) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+                let Some(scan) = self.gate_scan(request, ctx) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+
+                packet
+                    .set_response(PacketResponse::Ok)
+                    .set_data(&encode(&scan))
+            },
+            PacketRequest::TransferTicket => {
+                let Some(request) = decode::<TransferRequest>(&packet) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+                if request.to.is_empty() || request.passenger_id.as_ref() == Some(&request.to) {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                }
+
+                let flight_num = request.flight_num;
+
+                // New owner's ticket is handed over by the previous owner
+                match self.transfer_ticket(request, ctx){
+                    Ok(ticket) => packet
+                        .set_response(PacketResponse::Ok)
+                        .set_data(&encode(&OwnedTicket{ flight_num, ticket: self.issued(flight_num, ticket) })),
+                    Err(rejection) => rejection.into_packet(packet.request()),
+                }
+            },
+            PacketRequest::ChangeSeat => {
+                let Some(request) = decode::<ChangeSeatRequest>(&packet) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+
+                let flight_num = request.flight_num;
+
+                match self.change_seat(request, ctx){
+                    Ok(ticket) => packet
+                        .set_response(PacketResponse::Ok)
+                        .set_data(&encode(&self.issued(flight_num, ticket))),
+                    Err(rejection) => rejection.into_packet(packet.request()),
+                }
+            },
+            PacketRequest::CancelTicket => {
+                let Some(request) = decode::<BookingRequest>(&packet) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+
+                match self.cancel_ticket(request, ctx){
+                    Ok(()) => packet.set_response(PacketResponse::Ok),
+                    Err(rejection) => rejection.into_packet(packet.request()),
+                }
+            },
+            PacketRequest::MyTickets => {
+                let Some(request) = decode::<TicketsRequest>(&packet) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+                let owner = TicketOwner{ passenger_id: request.passenger_id, ip: ctx.peer_addr().ip() };
+
+                // Only tickets the passenger proves with their secrets are returned
+                let mut tickets = Vec::new();
+                for key in request.tickets{
+                    let Some(flight) = self.db_storage.flight(key.flight_num) else { continue; };
+                    let flight = flight.lock().unwrap();
+
+                    if let Ok(index) = flight.owned_ticket(&key.booking_ref, &owner, key.secret) {
+                        tickets.push(OwnedTicket{ flight_num: key.flight_num, ticket: self.issued(key.flight_num, flight.sold[index].clone()) });
+                    }
+                }
+
+                packet
+                    .set_response(PacketResponse::Ok)
+                    .set_data(&encode(&tickets))
+            },
+            PacketRequest::CheckIn => {
+                let Some(request) = decode::<BookingRequest>(&packet) else {
+                    return packet.set_response(PacketResponse::ErrorInRequest);
+                };
+
+                match self.check_in(request, ctx){
+                    Ok(pass) => packet
+                        .set_response(PacketResponse::Ok)
+                        .set_data(&encode(&pass)),
+                    Err(rejection) => rejection.into_packet(packet.request()),
+                }
+            },
+        }
+    }
+
+    /// Returns response payload of a sold ticket, sent only to its owner
+    fn issued(&self, flight_num: u32, ticket: SoldTicket) -> IssuedTicket{
+        IssuedTicket{
+            signed: Some(self.signer.sign(flight_num, &ticket)),
+            seat: ticket.seat,
+            fare: ticket.fare,
+            booking_ref: ticket.booking_ref,
+            secret: Some(ticket.secret),
+        }
+    }
+
+    /// Issues a ticket if quotas allow it, `None` if flight (or the requested class) is sold out or unknown
+    fn request_ticket(&self, request: TicketRequest, ctx: &RequestContext) -> std::result::Result<Option<SoldTicket>, Rejection>{
+        let Some(flight) = self.db_storage.flight(request.flight_num) else { return Ok(None); };
+
+        let owner = TicketOwner{ passenger_id: request.passenger_id.clone(), ip: ctx.peer_addr().ip() };
+        let keys = owner.keys();
+
+        let mut flight = flight.lock().unwrap();
+        flight.release_expired(ctx.received_at());
+
+        self.check_access(&mut flight, &request, &owner, ctx)?;
+
+        // Sold out flight sells standby tickets priced as its sold seats
+        let index = flight.pick_seat(request.class);
+        let priced_seat = match index{
+            Some(index) => flight.seats[index].clone(),
+            None => match flight.standby_fare_seat(request.class){
+                Some(seat) => seat,
+                None => return Ok(None),
+            },
+        };
+
+        let mut fare = self.pricing.fare(&flight, &priced_seat, ctx.received_at());
+
+        self.charge(&mut fare, request.promo_code.as_deref(), request.flight_num, &keys, ctx)?;
+
+        let ticket = match index{
+            Some(index) => flight.sell_seat_at(index, owner.clone(), fare, request.promo_code.clone()),
+            None => flight.sell_standby(owner.clone(), fare, request.promo_code.clone()),
+        };
+        match &ticket{
+            Some(_) => Self::use_admission(&mut flight, &request, &owner),
+            None => {
+                self.quotas.release(&keys);
+                if let Some(code) = &request.promo_code { self.promos.refund(code); }
+            },
+        }
+
+        Ok(ticket)
+    }
+
+    /// Sells a seat on every leg or none, `None` if any leg is sold out or unknown
+    /// 
+    /// Flights are locked in order of flight number and stay locked until every leg is sold,
+    /// so concurrent itineraries never deadlock and never see a partial booking
+    fn book_itinerary(&self, request: ItineraryRequest, ctx: &RequestContext) -> std::result::Result<Option<BookedItinerary>, Rejection>{
+        let mut order: Vec<usize> = (0..request.legs.len()).collect();
+        order.sort_by_key(|&i| request.legs[i].leg.flight_num);
+
+        let mut handles = Vec::new();
+        for &i in &order{
+            let Some(flight) = self.db_storage.flight(request.legs[i].leg.flight_num) else { return Ok(None); };
+            handles.push(flight);
+        }
+
+        let owner = TicketOwner{ passenger_id: request.passenger_id.clone(), ip: ctx.peer_addr().ip() };
+        let keys = owner.keys();
+
+        let mut flights: Vec<_> = handles.iter().map(|f| f.lock().unwrap()).collect();
+
+        // Nothing is sold until every leg has a seat
+        let mut picked = Vec::new();
+        for (flight, &i) in flights.iter_mut().zip(&order){
+            let leg = &request.legs[i];
+            let ticket_request = TicketRequest{
+                flight_num: leg.leg.flight_num,
+                passenger_id: request.passenger_id.clone(),
+                queue_token: leg.queue_token,
+                class: leg.leg.class,
+                promo_code: None,
+            };
+
+            flight.release_expired(ctx.received_at());
+
+            self.check_access(flight, &ticket_request, &owner, ctx)?;
+
+            let Some(index) = flight.pick_seat(leg.leg.class) else { return Ok(None); };
+            picked.push((index, self.pricing.fare(flight, &flight.seats[index], ctx.received_at())));
+        }
+
+        for reserved in 0..picked.len(){
+            if let Err(rejection) = self.quotas.reserve(&keys) {
+                for _ in 0..reserved { self.quotas.release(&keys); }
+                return Err(rejection);
+            }
+        }
+
+        let booking_ref = loop {
+            let booking_ref = new_booking_ref();
+            if flights.iter().all(|f| f.ticket_by_ref(&booking_ref).is_none()) { break booking_ref; }
+        };
+
+        let mut legs = vec![None; request.legs.len()];
+        for ((flight, &i), (index, fare)) in flights.iter_mut().zip(&order).zip(picked){
+            let flight_num = flight.info.num;
+            let ticket = flight.sell_seat_with_ref(index, owner.clone(), fare, booking_ref.clone())
+                .expect("picked seat is free under the lock");
+            if let Some(room) = &mut flight.waiting_room {
+                room.use_admission(request.legs[i].queue_token, &owner);
+            }
+
+            legs[i] = Some(OwnedTicket{ flight_num, ticket: self.issued(flight_num, ticket) });
+        }
+
+        Ok(Some(BookedItinerary{ booking_ref, legs: legs.into_iter().flatten().collect() }))
+    }
+
+    /// Holds a seat at the current price, `None` if flight (or the requested class) is sold out or unknown
+    fn hold_ticket(&self, request: TicketRequest, ctx: &RequestContext) -> std::result::Result<Option<SeatHold>, Rejection>{
+        let Some(flight) = self.db_storage.flight(request.flight_num) else { return Ok(None); };
+
+        let owner = TicketOwner{ passenger_id: request.passenger_id.clone(), ip: ctx.peer_addr().ip() };
+        let now = ctx.received_at();
+
+        let mut flight = flight.lock().unwrap();
+        flight.release_expired(now);
+
+        self.check_access(&mut flight, &request, &owner, ctx)?;
+
+        // Open holds are capped per owner and count as tickets for the overall quota
+        let keys = owner.keys();
+        let open = keys.iter().map(|k| flight.holds_of(k)).max().unwrap_or(0);
+        if open >= MAX_OPEN_HOLDS {
+            return Err(Rejection::QuotaExceeded{ scope: QuotaScope::Flight, limit: MAX_OPEN_HOLDS });
+        }
+        self.quotas.check(&keys, open)?;
+
+        let Some(index) = flight.pick_seat(request.class) else { return Ok(None); };
+        let fare = self.pricing.fare(&flight, &flight.seats[index], now);
+
+        let hold = flight.hold_seat_at(index, owner.clone(), fare, now + self.pricing.hold_time());
+        if hold.is_some() { Self::use_admission(&mut flight, &request, &owner); }
+
+        Ok(hold)
+    }
+
+    /// Sells the held seat for the held fare if quotas allow it
+    fn buy_held(&self, request: BuyRequest, ctx: &RequestContext) -> std::result::Result<SoldTicket, Rejection>{
+        let Some(flight) = self.db_storage.flight(request.flight_num) else { return Err(Rejection::HoldExpired); };
+
+        let owner = TicketOwner{ passenger_id: request.passenger_id, ip: ctx.peer_addr().ip() };
+        let keys = owner.keys();
+
+        let mut flight = flight.lock().unwrap();
+
+        let mut hold = flight.take_hold(request.hold_id, &owner, ctx.received_at())?;
+
+        let checked = flight.check_open()
+            .and_then(|_| self.check_flight_quota(&flight, &keys))
+            .and_then(|_| self.charge(&mut hold.fare, request.promo_code.as_deref(), request.flight_num, &keys, ctx));
+
+        if let Err(rejection) = checked {
+            flight.restore_hold(hold);
+            return Err(rejection);
+        }
+
+        Ok(flight.sell_held(hold, request.promo_code))
+    }
+
+    /// Applies the promo code to the fare and counts the ticket in quotas
+    /// 
+    /// Called under the flight lock right before the sale, 
+    /// so code use is counted only for issued tickets
+    fn charge(&self, fare: &mut Fare, promo_code: Option<&str>, flight_num: u32, keys: &[String], ctx: &RequestContext) -> std::result::Result<(), Rejection>{
+        if let Some(code) = promo_code {
+            fare.price = self.promos.redeem(code, flight_num, ctx.received_at())?.apply(fare.price);
+        }
+
+        if let Err(rejection) = self.quotas.reserve(keys) {
+            if let Some(code) = promo_code { self.promos.refund(code); }
+            return Err(rejection);
+        }
+
+        Ok(())
+    }
+
+    /// Boards the passenger of the scanned code, `None` if flight does not exist
+    fn gate_scan(&self, request: ScanRequest, ctx: &RequestContext) -> Option<GateScan>{
+        let flight = self.db_storage.flight(request.flight_num)?;
+
+        let claims = scanned_ticket(&request.code).and_then(|ticket| self.signer.verify(&ticket));
+
+        let mut flight = flight.lock().unwrap();
+
+        let result = match &claims{
+            None => ScanResult::Invalid,
+            Some(claims) if claims.flight_num != request.flight_num => ScanResult::WrongFlight,
+            Some(claims) => flight.board(claims, ctx.received_at()),
+        };
+
+        let seat = claims
+            .filter(|_| matches!(result, ScanResult::Accept | ScanResult::AlreadyBoarded))
+            .map(|c| c.seat);
+
+        Some(GateScan{ result, seat, count: flight.boarding_count() })
+    }
+
+    /// Gives the ticket to the passenger with `request.to` ID
+    /// 
+    /// New owner's quotas have to allow one more ticket, returns the ticket with its new secret
+    fn transfer_ticket(&self, request: TransferRequest, ctx: &RequestContext) -> std::result::Result<SoldTicket, Rejection>{
+        let Some(flight) = self.db_storage.flight(request.flight_num) else { return Err(Rejection::TicketNotFound); };
+
+        let owner = TicketOwner{ passenger_id: request.passenger_id, ip: ctx.peer_addr().ip() };
+        let recipient = TicketOwner::by_id(request.to);
+
+        // Keys shared by both owners keep counting the ticket, the sender's address stops counting it
+        let (owner_keys, new_keys) = (owner.keys(), recipient.keys());
+        let recipient_keys: Vec<String> = new_keys.iter().filter(|k| !owner_keys.contains(k)).cloned().collect();
+        let released: Vec<String> = owner_keys.into_iter().filter(|k| !new_keys.contains(k)).collect();
+
+        let mut flight = flight.lock().unwrap();
+
+        flight.owned_ticket(&request.booking_ref, &owner, request.secret)?;
+
+        self.check_flight_quota(&flight, &recipient_keys)?;
+        self.quotas.reserve(&recipient_keys)?;
+
+        let ticket = match flight.transfer(&request.booking_ref, &owner, request.secret, recipient, ctx.received_at()){
+            Ok(ticket) => ticket,
+            Err(rejection) => {
+                self.quotas.release(&recipient_keys);
+                return Err(rejection);
+            },
+        };
+
+        self.quotas.release(&released);
+
+        Ok(ticket)
+    }
+
+    /// Cancels the owned ticket and refunds it
+    fn cancel_ticket(&self, request: BookingRequest, ctx: &RequestContext) -> std::result::Result<(), Rejection>{
+        let Some(flight) = self.db_storage.flight(request.flight_num) else { return Err(Rejection::TicketNotFound); };
+
+        let owner = TicketOwner{ passenger_id: request.passenger_id, ip: ctx.peer_addr().ip() };
+
+        let ticket = flight.lock().unwrap().cancel(&request.booking_ref, &owner, request.secret)?;
+        self.refund(&ticket);
+
+        Ok(())
+    }
+
+    /// Takes the ticket out of quotas and promo code uses
+    pub fn refund(&self, ticket: &SoldTicket){
+        self.quotas.release(&ticket.owner.keys());
+
+        if let Some(code) = &ticket.promo_code { self.promos.refund(code); }
+    }
+
+    /// Swaps the owned seat for the chosen free one under the flight lock
+    fn change_seat(&self, request: ChangeSeatRequest, ctx: &RequestContext) -> std::result::Result<SoldTicket, Rejection>{
+        let Some(flight) = self.db_storage.flight(request.flight_num) else { return Err(Rejection::TicketNotFound); };
+
+        let owner = TicketOwner{ passenger_id: request.passenger_id, ip: ctx.peer_addr().ip() };
+
+        let mut flight = flight.lock().unwrap();
+
+        flight.change_seat(&request.booking_ref, &owner, request.secret, &request.seat, ctx.received_at())
+    }
+
+    /// Checks in the ticket within the check-in window and returns its boarding pass
+    fn check_in(&self, request: BookingRequest, ctx: &RequestContext) -> std::result::Result<BoardingPass, Rejection>{
+        let Some(flight) = self.db_storage.flight(request.flight_num) else { return Err(Rejection::TicketNotFound); };
+
+        let owner = TicketOwner{ passenger_id: request.passenger_id, ip: ctx.peer_addr().ip() };
+        let window = *self.check_in.lock().unwrap();
+
+        let mut flight = flight.lock().unwrap();
+        let departure = flight.info.departure;
+
+        if flight.closed { return Err(Rejection::CheckInClosed); }
+        window.check(departure, ctx.received_at())?;
+
+        let ticket = flight.check_in(&request.booking_ref, &owner, request.secret, ctx.received_at())?;
+
+        Ok(BoardingPass{
+            flight_num: request.flight_num,
+            ticket: self.signer.sign(request.flight_num, &ticket),
+            check_in: ticket.check_in.expect("ticket is checked in"),
+            seat: ticket.seat,
+            booking_ref: ticket.booking_ref,
+            passenger_id: ticket.owner.passenger_id,
+            departure,
+        })
+    }
+
+    /// Uses up the waiting room admission the seat was given with
+    fn use_admission(flight: &mut FlightDB, request: &TicketRequest, owner: &TicketOwner){
+        if let Some(room) = &mut flight.waiting_room {
+            room.use_admission(request.queue_token, owner);
+        }
+    }
+
+    /// Checks sales window, waiting room, lottery and per-flight quota of the flight
+    fn check_access(&self, flight: &mut FlightDB, request: &TicketRequest, owner: &TicketOwner, ctx: &RequestContext) -> std::result::Result<(), Rejection>{
+        flight.check_open()?;
+        flight.info.check_sale(ctx.received_at())?;
+
+        if let Some(room) = &mut flight.waiting_room {
+            room.check(request.queue_token, owner, ctx.received_instant())?;
+        }
+
+        if flight.lottery.as_ref().is_some_and(|l| l.is_pending()) {
+            return Err(Rejection::LotteryOnly);
+        }
+
+        self.check_flight_quota(flight, &owner.keys())
+    }
+
+    pub(crate) fn check_flight_quota(&self, flight: &FlightDB, keys: &[String]) -> std::result::Result<(), Rejection>{
+        if let Some(limit) = self.quotas.rules().per_flight {
+            if keys.iter().any(|k| flight.tickets_of(k) >= limit) {
+                return Err(Rejection::QuotaExceeded{ scope: QuotaScope::Flight, limit });
+            }
+        }
+
+        Ok(())
+    }
+}
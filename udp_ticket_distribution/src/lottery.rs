@@ -0,0 +1,178 @@
+use std::time::SystemTime;
+use super::*;
+use super::rng::SeededRng;
+use super::pricing::Pricing;
+
+/// Lottery of a flight's seats
+/// 
+/// While the lottery is not drawn the flight can't be bought first-come-first-served,
+/// seats left after the draw are sold as usual
+#[derive(Debug, Clone)]
+pub struct Lottery{
+    /// Entries are accepted until this time
+    pub entries_close: SystemTime,
+    /// Registered passengers in order of registration
+    pub entries: Vec<TicketOwner>,
+    /// Result of the draw
+    pub draw: Option<LotteryDraw>,
+}
+
+/// Result of a lottery draw, enough to reproduce it
+#[derive(Debug, Clone)]
+pub struct LotteryDraw{
+    /// Seed the entries were shuffled with
+    pub seed: u64,
+    /// Number of entries taking part in the draw
+    pub entries_num: usize,
+    /// Winners and their seats in order of drawing
+    pub winners: Vec<SoldTicket>,
+    /// Drawn entries refused by ticket quotas, in order of drawing
+    pub skipped: Vec<TicketOwner>,
+}
+
+impl LotteryDraw{
+    /// Returns entries in the order a draw with `seed` takes them
+    pub fn order(seed: u64, entries: &[TicketOwner]) -> Vec<TicketOwner>{
+        let mut order = entries.to_vec();
+        SeededRng::new(seed).shuffle(&mut order);
+
+        order
+    }
+
+    /// Replays the draw with its seed, `true` if `entries` give the same result
+    /// 
+    /// Drawn entries have to be winners or skipped in order of drawing,
+    /// entries drawn after them were left without seats
+    pub fn verify(&self, entries: &[TicketOwner]) -> bool{
+        if entries.len() != self.entries_num { return false; }
+
+        let mut winners = self.winners.iter().map(|w| &w.owner).peekable();
+        let mut skipped = self.skipped.iter().peekable();
+
+        for owner in Self::order(self.seed, entries){
+            if winners.next_if(|w| **w == owner).is_none() && skipped.next_if(|s| **s == owner).is_none() {
+                break;
+            }
+        }
+
+        winners.peek().is_none() && skipped.peek().is_none()
+    }
+}
+
+/// Lottery state of a passenger
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LotteryStatus{
+    /// Passenger did not enter the lottery
+    NotEntered,
+    /// Lottery is not drawn yet
+    Pending,
+    /// Passenger won the seat
+    Won{ 
+        seat: String, 
+        #[serde(default)] 
+        fare: Fare, 
+        /// Signed ticket, filled in by the distributor
+        #[serde(default)] 
+        ticket: Option<SignedTicket>,
+        /// Ownership secret of the ticket, filled in by the distributor
+        #[serde(default)] 
+        secret: Option<u64>,
+    },
+    /// Passenger was not drawn
+    Lost,
+}
+
+impl Lottery{
+    /// Returns lottery accepting entries until `entries_close`
+    pub fn new(entries_close: SystemTime) -> Self{
+        Lottery { entries_close, entries: Vec::new(), draw: None }
+    }
+
+    /// Returns `true` if the lottery still blocks first-come-first-served sales
+    pub fn is_pending(&self) -> bool{ self.draw.is_none() }
+
+    /// Registers the owner, entries sharing passenger ID or address with an earlier one are ignored
+    pub(crate) fn enter(&mut self, owner: TicketOwner, now: SystemTime) -> std::result::Result<(), Rejection>{
+        if self.draw.is_some() || now >= self.entries_close {
+            return Err(Rejection::LotteryClosed);
+        }
+
+        let keys = owner.keys();
+        if !self.entries.iter().any(|e| e.keys().iter().any(|k| keys.contains(k))) {
+            self.entries.push(owner);
+        }
+
+        Ok(())
+    }
+
+    /// Returns status of the owner
+    pub(crate) fn status(&self, owner: &TicketOwner) -> LotteryStatus{
+        let Some(entry) = self.find(owner) else { return LotteryStatus::NotEntered; };
+
+        let Some(draw) = &self.draw else { return LotteryStatus::Pending; };
+
+        match draw.winners.iter().find(|w| &w.owner == entry){
+            Some(winner) => LotteryStatus::Won{ seat: winner.seat.clone(), fare: winner.fare, ticket: None, secret: None },
+            None => LotteryStatus::Lost,
+        }
+    }
+
+    /// Shuffles entries with the seed, first ones get seats
+    /// 
+    /// Winners pay the price of the current pricing policy at `now`, as in other sales.
+    /// Entries refused by `eligible` are skipped, it is asked only while seats are left
+    pub(crate) fn draw(&mut self, flight: &mut FlightDB, seed: u64, pricing: &Pricing, now: SystemTime, mut eligible: impl FnMut(&FlightDB, &TicketOwner) -> bool) -> LotteryDraw{
+        let mut winners = Vec::new();
+        let mut skipped = Vec::new();
+
+        for owner in LotteryDraw::order(seed, &self.entries){
+            let Some(index) = flight.pick_seat(None) else { break; };
+
+            if !eligible(flight, &owner) {
+                skipped.push(owner);
+                continue;
+            }
+
+            let fare = pricing.fare(flight, &flight.seats[index], now);
+            let Some(ticket) = flight.sell_seat_at(index, owner, fare, None) else { break; };
+            winners.push(ticket);
+        }
+
+        let draw = LotteryDraw{ seed, entries_num: self.entries.len(), winners, skipped };
+        self.draw = Some(draw.clone());
+
+        draw
+    }
+
+    fn find(&self, owner: &TicketOwner) -> Option<&TicketOwner>{
+        self.entries.iter().find(|e| e.same_as(owner))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::net::IpAddr;
+    use std::sync::Arc;
+
+    #[test]
+    fn winners_pay_policy_price(){
+        let mut flight = FlightDB::new(1, vec!["A1".to_string(), "B1".to_string()]);
+        flight.set_fare(SeatRange::Rows(1..=1), Fare{ class: FareClass::Economy, price: 1000 });
+
+        let pricing = Pricing::default();
+        pricing.set_policy(Some(Arc::new(|input: &PricingInput| input.base.price + input.seats_left as u64)));
+
+        let mut lottery = Lottery::new(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1));
+        for (i, id) in ["a", "b", "c"].into_iter().enumerate(){
+            let owner = TicketOwner{ passenger_id: Some(id.to_string()), ip: IpAddr::from([127, 0, 0, i as u8 + 1]) };
+            lottery.enter(owner, SystemTime::UNIX_EPOCH).unwrap();
+        }
+
+        let draw = lottery.draw(&mut flight, 7, &pricing, SystemTime::UNIX_EPOCH, |_, _| true);
+        let prices: Vec<u64> = draw.winners.iter().map(|w| w.fare.price).collect();
+
+        assert_eq!(prices, vec![1002, 1001]);
+        assert_eq!(flight.revenue(), 2003);
+    }
+}
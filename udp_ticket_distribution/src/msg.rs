@@ -0,0 +1,193 @@
+//! Payloads of requests and responses between `Passenger` and `Distributor`
+//! 
+//! Every payload is serialized with serde_cbor, 
+//! new fields have to be `#[serde(default)]` to keep older payloads readable
+
+use serde::de::DeserializeOwned;
+use super::*;
+
+/// Serializes payload to bytes
+pub(crate) fn encode<T: Serialize>(msg: &T) -> Vec<u8>{
+    let mut bytearr = Vec::new();
+
+    serde_cbor::to_writer(&mut bytearr, msg).unwrap();
+
+    bytearr
+}
+
+/// Deserializes payload from packet data, `None` if packet has no valid payload
+pub(crate) fn decode<T: DeserializeOwned>(packet: &UdpPacket) -> Option<T>{
+    let data = packet.try_retrieve_data().ok()?;
+
+    serde_cbor::from_reader(data.as_slice()).ok()
+}
+
+/// Returns flight number of a `RequestTicket` sent in the original format, 
+/// 4 bytes of little-endian flight number answered with bare seat bytes
+pub(crate) fn legacy_flight_num(packet: &UdpPacket) -> Option<u32>{
+    let data = packet.try_retrieve_data().ok()?;
+
+    Some(u32::from_le_bytes(data.as_slice().try_into().ok()?))
+}
+
+/// Payload of `RequestTicket` and `HoldTicket`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TicketRequest{
+    pub flight_num: u32,
+    #[serde(default)]
+    pub passenger_id: Option<String>,
+    #[serde(default)]
+    pub queue_token: Option<u64>,
+    #[serde(default)]
+    pub class: Option<FareClass>,
+    #[serde(default)]
+    pub promo_code: Option<String>,
+}
+
+/// Payload of `RequestTicket` response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct IssuedTicket{
+    pub seat: String,
+    pub fare: Fare,
+    #[serde(default)]
+    pub booking_ref: String,
+    #[serde(default)]
+    pub signed: Option<SignedTicket>,
+    /// Ownership secret, sent only to the owner
+    #[serde(default)]
+    pub secret: Option<u64>,
+}
+
+/// Payload of requests about a flight without extra data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct FlightRequest{
+    pub flight_num: u32,
+    #[serde(default)]
+    pub passenger_id: Option<String>,
+}
+
+/// Payload of `QueuePosition`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct QueueRequest{
+    pub flight_num: u32,
+    #[serde(default)]
+    pub passenger_id: Option<String>,
+    pub token: u64,
+}
+
+/// Payload of `BuyHeld`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct BuyRequest{
+    pub flight_num: u32,
+    #[serde(default)]
+    pub passenger_id: Option<String>,
+    pub hold_id: u64,
+    #[serde(default)]
+    pub promo_code: Option<String>,
+}
+
+/// Payload of requests about a sold ticket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct BookingRequest{
+    pub flight_num: u32,
+    #[serde(default)]
+    pub passenger_id: Option<String>,
+    pub booking_ref: String,
+    #[serde(default)]
+    pub secret: u64,
+}
+
+/// Payload of `GateScan`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ScanRequest{
+    pub flight_num: u32,
+    pub code: Vec<u8>,
+}
+
+/// Payload of `TransferTicket`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TransferRequest{
+    pub flight_num: u32,
+    #[serde(default)]
+    pub passenger_id: Option<String>,
+    pub booking_ref: String,
+    #[serde(default)]
+    pub secret: u64,
+    /// Passenger ID of the new owner
+    pub to: String,
+}
+
+/// Payload of `ChangeSeat`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ChangeSeatRequest{
+    pub flight_num: u32,
+    #[serde(default)]
+    pub passenger_id: Option<String>,
+    pub booking_ref: String,
+    #[serde(default)]
+    pub secret: u64,
+    /// Free seat the ticket is moved to
+    pub seat: String,
+}
+
+/// Leg of `BookItinerary` with the queue token of its flight
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct LegRequest{
+    pub leg: ItineraryLeg,
+    #[serde(default)]
+    pub queue_token: Option<u64>,
+}
+
+/// Payload of `BookItinerary`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ItineraryRequest{
+    #[serde(default)]
+    pub passenger_id: Option<String>,
+    pub legs: Vec<LegRequest>,
+}
+
+/// Payload of `BookItinerary` response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct BookedItinerary{
+    pub booking_ref: String,
+    pub legs: Vec<OwnedTicket>,
+}
+
+/// Sold ticket named by its owner, `secret` proves the ownership
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TicketKey{
+    pub flight_num: u32,
+    pub booking_ref: String,
+    pub secret: u64,
+}
+
+/// Payload of `MyTickets`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TicketsRequest{
+    #[serde(default)]
+    pub passenger_id: Option<String>,
+    #[serde(default)]
+    pub tickets: Vec<TicketKey>,
+}
+
+/// Ticket of `MyTickets` and `TransferTicket` responses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct OwnedTicket{
+    pub flight_num: u32,
+    pub ticket: IssuedTicket,
+}
+
+/// Any request payload reduced to passenger identity
+/// 
+/// Every request payload names the field `passenger_id`, other fields are ignored
+#[derive(Debug, Clone, Default, Deserialize)]
+pub(crate) struct PassengerTag{
+    #[serde(default)]
+    pub passenger_id: Option<String>,
+}
+
+/// Payload of `RateLimited` response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct RetryAfter{
+    pub retry_after_ms: u64,
+}
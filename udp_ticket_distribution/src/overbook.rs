@@ -0,0 +1,51 @@
+use super::*;
+
+/// Result of `Distributor::resolve_overbooking`
+#[derive(Debug, Clone, Default)]
+pub struct OverbookingResolution{
+    /// Standby tickets which got a free seat
+    pub reassigned: Vec<SoldTicket>,
+    /// Standby tickets denied boarding, their sale is undone
+    pub bumped: Vec<SoldTicket>,
+}
+
+/// Returns number of tickets `percent` of the capacity allows to oversell
+pub(crate) fn allowance(capacity: usize, percent: u8) -> u32{
+    (capacity * percent as usize / 100) as u32
+}
+
+/// Returns seat code of the n-th standby ticket
+pub(crate) fn standby_seat(n: u32) -> String{
+    format!("SB{}", n)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::net::IpAddr;
+
+    #[test]
+    fn standby_gets_freed_seat(){
+        let owner = |id: &str| TicketOwner{ passenger_id: Some(id.to_string()), ip: IpAddr::from([127, 0, 0, 1]) };
+
+        let mut flight = FlightDB::new(1, vec!["A1".to_string()]);
+        flight.overbooking = allowance(10, 20);
+        assert_eq!(flight.overbooking, 2);
+
+        let sold = flight.sell_seat(owner("first")).unwrap();
+        let standby = flight.sell_standby(owner("second"), Fare::default(), None).unwrap();
+        flight.sell_standby(owner("third"), Fare::default(), None).unwrap();
+        assert_eq!((standby.seat.as_str(), standby.standby), ("SB1", true));
+        assert!(flight.sell_standby(owner("fourth"), Fare::default(), None).is_none());
+
+        flight.cancel(&sold.booking_ref, &owner("first"), sold.secret).unwrap();
+        assert_eq!(flight.ticket_by_ref(&standby.booking_ref).unwrap().seat, "A1");
+        assert_eq!(flight.info.seats_num, 0);
+
+        let resolution = flight.resolve_overbooking(SystemTime::now());
+        assert!(resolution.reassigned.is_empty());
+        assert_eq!(resolution.bumped[0].owner, owner("third"));
+        assert_eq!(flight.standby_count(), 0);
+        assert_eq!(flight.denied.len(), 1);
+    }
+}
@@ -1,24 +1,58 @@
+use std::collections::HashMap;
 use std::io::{Error, ErrorKind};
+use std::time::Duration;
 use super::*;
+use super::msg::*;
 
 /// Implements passenger's role in the process of acquiring tickets
 pub struct Passenger{
     udp_client: ClientSide,
-    acquired_tickets: Vec<FlightTicket>
+    acquired_tickets: Vec<FlightTicket>,
+    id: Option<String>,
+    queue_tokens: HashMap<u32, u64>,
 }
 
 impl Passenger{
     /// Returns `Result<Passenger>` if socket binding was successful
     pub async fn new() -> Result<Self>{
+        Self::with_client(ClientSide::new_with_address(Auto).await?)
+    }
+
+    /// Returns `Result<Passenger>` with socket bound to `addr`
+    pub async fn new_with_address(addr: String) -> Result<Self>{
+        Self::with_client(ClientSide::new_with_address(Manual(addr)).await?)
+    }
+
+    fn with_client(udp_client: ClientSide) -> Result<Self>{
         Ok(Passenger { 
-            udp_client: ClientSide::new_with_address(Auto).await?, 
+            udp_client, 
             acquired_tickets: vec![], 
+            id: None,
+            queue_tokens: HashMap::new(),
         })
     }
 
+    /// Sets passenger's identity which is sent with requests
+    /// 
+    /// Distributor uses it for per-passenger limits, 
+    /// without it passenger is known only by address
+    pub fn set_id(&mut self, id: impl Into<String>){
+        self.id = Some(id.into());
+    }
+
+    /// Returns passenger's identity
+    pub fn id(&self) -> Option<&str>{ self.id.as_deref() }
+
     /// Returns all previosly acquired tickets
     pub fn acquired_tickets(&self) -> Vec<FlightTicket> {self.acquired_tickets.to_vec()}
 
+    /// Makes passenger resend requests answered with `Busy` by an overloaded distributor
+    /// 
+    /// See `ClientSide::set_busy_retry`
+    pub fn set_busy_retry(&mut self, retries: u32, delay: Duration){
+        self.udp_client.set_busy_retry(retries, delay);
+    }
+
     /// Attemp to ping specified distributor address
     pub async fn try_connect(&mut self, serv_addr: &String) -> Result<()>{
         self.udp_client.set_server(Manual( serv_addr.to_owned() )).await
@@ -36,40 +70,484 @@ impl Passenger{
 
                 Ok(convert_to_flightinfo(&data))
             },
-            _ => Err(Error::new(ErrorKind::InvalidData, "Response is invalid")),
+            _ => Err(response_error(&packet)),
         }
     }
 
     /// Query a ticket from distributor
     /// 
-    /// Returns `None` if there is no tickets are available for this flight
+    /// Returns `None` if there is no tickets are available for this flight.
+    /// Queue token of the flight is sent with the request, 
+    /// see `Passenger::wait_for_admission`
     pub async fn query_ticket_for_a_flight(&mut self, flight_num: u32) -> Result<Option<String>>{
+        let ticket = self.request_ticket(flight_num, None, None).await?;
+
+        Ok(ticket.map(|t| t.ticket))
+    }
+
+    /// Query the cheapest ticket of the fare class
+    /// 
+    /// Returns `None` if there is no tickets of this class available
+    pub async fn query_ticket_in_class(&mut self, flight_num: u32, class: FareClass) -> Result<Option<FlightTicket>>{
+        self.request_ticket(flight_num, Some(class), None).await
+    }
+
+    /// Query a ticket discounted with the promo code, `class` picks the cheapest seat of the class
+    /// 
+    /// Fails with `Rejection::PromoInvalid` if the code is not accepted, no ticket is issued then
+    pub async fn query_ticket_with_promo(&mut self, flight_num: u32, class: Option<FareClass>, promo_code: &str) -> Result<Option<FlightTicket>>{
+        self.request_ticket(flight_num, class, Some(promo_code.to_string())).await
+    }
+
+    async fn request_ticket(&mut self, flight_num: u32, class: Option<FareClass>, promo_code: Option<String>) -> Result<Option<FlightTicket>>{
+        let request = TicketRequest{ 
+            flight_num, 
+            passenger_id: self.id.clone(), 
+            queue_token: self.queue_tokens.get(&flight_num).copied(),
+            class,
+            promo_code,
+        };
+
         let packet = 
             UdpPacket::new_with_request(PacketRequest::RequestTicket)
-                .set_data(&flight_num.to_le_bytes());
+                .set_data(&encode(&request));
 
         let packet = self.udp_client.send_and_recv(packet).await?;
         
         match packet.response() {
             PacketResponse::None | PacketResponse::TicketsSold => Ok(None),
             PacketResponse::Ok => {
-                let ticket = String::from_utf8( packet.try_retrieve_data().unwrap() ).unwrap();
+                let issued = decode::<IssuedTicket>(&packet)
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Response is invalid"))?;
 
-                self.acquired_tickets.push(FlightTicket{
-                    flight_num,
-                    ticket: ticket.clone()
-                });
+                let ticket = FlightTicket::from_issued(flight_num, issued);
+                self.acquired_tickets.push(ticket.clone());
 
                 Ok(Some(ticket))
             },
-            _ => Err(Error::new(ErrorKind::InvalidData, "Response is invalid")),
+            _ => Err(response_error(&packet)),
+        }
+    }
+
+    /// Books a seat on every leg or on none of them
+    /// 
+    /// Returns `None` if any leg is sold out, standby tickets of overbooked flights are not booked.
+    /// Promo codes are not applied to itineraries.
+    /// Tickets of all legs share one booking reference
+    pub async fn book_itinerary(&mut self, legs: &[ItineraryLeg]) -> Result<Option<Itinerary>>{
+        let request = ItineraryRequest{
+            passenger_id: self.id.clone(),
+            legs: legs.iter().map(|&leg| LegRequest{ 
+                leg, 
+                queue_token: self.queue_tokens.get(&leg.flight_num).copied(),
+            }).collect(),
+        };
+
+        let packet = 
+            UdpPacket::new_with_request(PacketRequest::BookItinerary)
+                .set_data(&encode(&request));
+
+        let packet = self.udp_client.send_and_recv(packet).await?;
+
+        match packet.response() {
+            PacketResponse::TicketsSold => Ok(None),
+            PacketResponse::Ok => {
+                let booked = decode::<BookedItinerary>(&packet)
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Response is invalid"))?;
+
+                let legs: Vec<FlightTicket> = booked.legs
+                    .into_iter()
+                    .map(|t| FlightTicket::from_issued(t.flight_num, t.ticket))
+                    .collect();
+
+                self.acquired_tickets.extend_from_slice(&legs);
+
+                Ok(Some(Itinerary{ booking_ref: booked.booking_ref, legs }))
+            },
+            _ => Err(response_error(&packet)),
+        }
+    }
+
+    /// Holds a seat at the price quoted now, `class` picks the cheapest seat of the class
+    /// 
+    /// Returns `None` if there is no tickets available.
+    /// Seat returns to sale if it is not bought with `Passenger::buy_held` until `TicketHold::expires`
+    pub async fn hold_ticket(&mut self, flight_num: u32, class: Option<FareClass>) -> Result<Option<TicketHold>>{
+        let request = TicketRequest{ 
+            flight_num, 
+            passenger_id: self.id.clone(), 
+            queue_token: self.queue_tokens.get(&flight_num).copied(),
+            class,
+            promo_code: None,
+        };
+
+        let packet = 
+            UdpPacket::new_with_request(PacketRequest::HoldTicket)
+                .set_data(&encode(&request));
+
+        let packet = self.udp_client.send_and_recv(packet).await?;
+
+        match packet.response() {
+            PacketResponse::None | PacketResponse::TicketsSold => Ok(None),
+            PacketResponse::Ok => decode::<TicketHold>(&packet)
+                .map(Some)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Response is invalid")),
+            _ => Err(response_error(&packet)),
+        }
+    }
+
+    /// Buys the held seat for the quoted fare
+    /// 
+    /// Fails with `Rejection::HoldExpired` if the hold is over
+    pub async fn buy_held(&mut self, hold: &TicketHold) -> Result<FlightTicket>{
+        self.buy(hold, None).await
+    }
+
+    /// Buys the held seat for the quoted fare discounted with the promo code
+    /// 
+    /// Hold is kept if the code is not accepted
+    pub async fn buy_held_with_promo(&mut self, hold: &TicketHold, promo_code: &str) -> Result<FlightTicket>{
+        self.buy(hold, Some(promo_code.to_string())).await
+    }
+
+    async fn buy(&mut self, hold: &TicketHold, promo_code: Option<String>) -> Result<FlightTicket>{
+        let request = BuyRequest{ 
+            flight_num: hold.flight_num, 
+            passenger_id: self.id.clone(), 
+            hold_id: hold.id,
+            promo_code,
+        };
+
+        let packet = 
+            UdpPacket::new_with_request(PacketRequest::BuyHeld)
+                .set_data(&encode(&request));
+
+        let packet = self.udp_client.send_and_recv(packet).await?;
+
+        match packet.response() {
+            PacketResponse::Ok => {
+                let issued = decode::<IssuedTicket>(&packet)
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Response is invalid"))?;
+
+                let ticket = FlightTicket::from_issued(hold.flight_num, issued);
+                self.acquired_tickets.push(ticket.clone());
+
+                Ok(ticket)
+            },
+            _ => Err(response_error(&packet)),
+        }
+    }
+
+    /// Gives the ticket to the passenger with ID `to`
+    /// 
+    /// Ticket is removed from acquired tickets. Returned ticket has a new secret,
+    /// hand it over to the new owner, who adds it with `Passenger::claim_ticket` from any address
+    pub async fn transfer_ticket(&mut self, ticket: &FlightTicket, to: impl Into<String>) -> Result<FlightTicket>{
+        let request = TransferRequest{ 
+            flight_num: ticket.flight_num, 
+            passenger_id: self.id.clone(), 
+            booking_ref: ticket.booking_ref.clone(),
+            secret: ticket.secret.unwrap_or_default(),
+            to: to.into(),
+        };
+
+        let packet = 
+            UdpPacket::new_with_request(PacketRequest::TransferTicket)
+                .set_data(&encode(&request));
+
+        let packet = self.udp_client.send_and_recv(packet).await?;
+
+        match packet.response() {
+            PacketResponse::Ok => {
+                let owned = decode::<OwnedTicket>(&packet)
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Response is invalid"))?;
+
+                self.acquired_tickets.retain(|t| t.flight_num != ticket.flight_num || t.booking_ref != ticket.booking_ref);
+
+                Ok(FlightTicket::from_issued(owned.flight_num, owned.ticket))
+            },
+            _ => Err(response_error(&packet)),
+        }
+    }
+
+    /// Moves the ticket to the free seat of the same fare class and not higher price
+    /// 
+    /// Old seat is kept until the new one is confirmed, 
+    /// acquired ticket is updated on success. Checked-in ticket has to be checked in again
+    pub async fn change_seat(&mut self, ticket: &FlightTicket, seat: impl Into<String>) -> Result<FlightTicket>{
+        let request = ChangeSeatRequest{ 
+            flight_num: ticket.flight_num, 
+            passenger_id: self.id.clone(), 
+            booking_ref: ticket.booking_ref.clone(),
+            secret: ticket.secret.unwrap_or_default(),
+            seat: seat.into(),
+        };
+
+        let packet = 
+            UdpPacket::new_with_request(PacketRequest::ChangeSeat)
+                .set_data(&encode(&request));
+
+        let packet = self.udp_client.send_and_recv(packet).await?;
+
+        match packet.response() {
+            PacketResponse::Ok => {
+                let issued = decode::<IssuedTicket>(&packet)
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Response is invalid"))?;
+
+                let changed = FlightTicket::from_issued(ticket.flight_num, issued);
+
+                for t in self.acquired_tickets.iter_mut(){
+                    if t.flight_num == changed.flight_num && t.booking_ref == changed.booking_ref {
+                        *t = changed.clone();
+                    }
+                }
+
+                Ok(changed)
+            },
+            _ => Err(response_error(&packet)),
         }
     }
+
+    /// Cancels the ticket, its seat goes to a standby passenger or back to sale
+    pub async fn cancel_ticket(&mut self, ticket: &FlightTicket) -> Result<()>{
+        let request = BookingRequest{ 
+            flight_num: ticket.flight_num, 
+            passenger_id: self.id.clone(), 
+            booking_ref: ticket.booking_ref.clone(),
+            secret: ticket.secret.unwrap_or_default(),
+        };
+
+        let packet = 
+            UdpPacket::new_with_request(PacketRequest::CancelTicket)
+                .set_data(&encode(&request));
+
+        let packet = self.udp_client.send_and_recv(packet).await?;
+
+        match packet.response() {
+            PacketResponse::Ok => {
+                self.acquired_tickets.retain(|t| t.flight_num != ticket.flight_num || t.booking_ref != ticket.booking_ref);
+
+                Ok(())
+            },
+            _ => Err(response_error(&packet)),
+        }
+    }
+
+    /// Refreshes acquired tickets from the distributor
+    /// 
+    /// Seats and signatures are updated, tickets no longer owned by the passenger are dropped.
+    /// This is how a standby ticket learns its assigned seat
+    pub async fn fetch_tickets(&mut self) -> Result<Vec<FlightTicket>>{
+        let keys = self.acquired_tickets
+            .iter()
+            .filter_map(|t| Some(TicketKey{ flight_num: t.flight_num, booking_ref: t.booking_ref.clone(), secret: t.secret? }))
+            .collect();
+
+        self.acquired_tickets = self.owned_tickets(keys).await?;
+
+        Ok(self.acquired_tickets())
+    }
+
+    /// Adds the ticket handed over by its previous owner, see `Passenger::transfer_ticket`
+    /// 
+    /// Fails with `Rejection::TicketNotFound` if the ticket was not given to this passenger
+    pub async fn claim_ticket(&mut self, flight_num: u32, booking_ref: &str, secret: u64) -> Result<FlightTicket>{
+        let key = TicketKey{ flight_num, booking_ref: booking_ref.to_string(), secret };
+
+        let Some(ticket) = self.owned_tickets(vec![key]).await?.pop() else {
+            return Err(Rejection::TicketNotFound.into());
+        };
+
+        self.acquired_tickets.retain(|t| t.flight_num != flight_num || t.booking_ref != booking_ref);
+        self.acquired_tickets.push(ticket.clone());
+
+        Ok(ticket)
+    }
+
+    async fn owned_tickets(&mut self, tickets: Vec<TicketKey>) -> Result<Vec<FlightTicket>>{
+        let packet = 
+            UdpPacket::new_with_request(PacketRequest::MyTickets)
+                .set_data(&encode(&TicketsRequest{ passenger_id: self.id.clone(), tickets }));
+
+        let packet = self.udp_client.send_and_recv(packet).await?;
+
+        match packet.response() {
+            PacketResponse::Ok => {
+                let tickets = decode::<Vec<OwnedTicket>>(&packet)
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Response is invalid"))?;
+
+                Ok(tickets
+                    .into_iter()
+                    .map(|t| FlightTicket::from_issued(t.flight_num, t.ticket))
+                    .collect())
+            },
+            _ => Err(response_error(&packet)),
+        }
+    }
+
+    /// Checks in the ticket and returns its boarding pass
+    /// 
+    /// Check-in is accepted within `CheckInWindow` before departure,
+    /// repeated check-in returns the same pass
+    pub async fn check_in(&mut self, ticket: &FlightTicket) -> Result<BoardingPass>{
+        let request = BookingRequest{ 
+            flight_num: ticket.flight_num, 
+            passenger_id: self.id.clone(), 
+            booking_ref: ticket.booking_ref.clone(),
+            secret: ticket.secret.unwrap_or_default(),
+        };
+
+        let packet = 
+            UdpPacket::new_with_request(PacketRequest::CheckIn)
+                .set_data(&encode(&request));
+
+        let packet = self.udp_client.send_and_recv(packet).await?;
+
+        match packet.response() {
+            PacketResponse::Ok => decode::<BoardingPass>(&packet)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Response is invalid")),
+            _ => Err(response_error(&packet)),
+        }
+    }
+
+    /// Registers the passenger in the lottery of a flight
+    /// 
+    /// Repeated registration is not an error
+    pub async fn enter_lottery(&mut self, flight_num: u32) -> Result<()>{
+        let packet = 
+            UdpPacket::new_with_request(PacketRequest::EnterLottery)
+                .set_data(&encode(&FlightRequest{ flight_num, passenger_id: self.id.clone() }));
+
+        let packet = self.udp_client.send_and_recv(packet).await?;
+
+        match packet.response() {
+            PacketResponse::Ok => Ok(()),
+            _ => Err(response_error(&packet)),
+        }
+    }
+
+    /// Asks distributor about passenger's lottery result
+    /// 
+    /// Won ticket is added to acquired tickets
+    pub async fn lottery_status(&mut self, flight_num: u32) -> Result<LotteryStatus>{
+        let packet = 
+            UdpPacket::new_with_request(PacketRequest::LotteryResult)
+                .set_data(&encode(&FlightRequest{ flight_num, passenger_id: self.id.clone() }));
+
+        let packet = self.udp_client.send_and_recv(packet).await?;
+
+        let status = match packet.response() {
+            PacketResponse::Ok => decode::<LotteryStatus>(&packet)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Response is invalid"))?,
+            _ => return Err(response_error(&packet)),
+        };
+
+        if let LotteryStatus::Won{ seat, fare, ticket, secret } = &status {
+            if !self.acquired_tickets.iter().any(|t| t.flight_num == flight_num && &t.ticket == seat) {
+                self.acquired_tickets.push(FlightTicket{ 
+                    flight_num, 
+                    ticket: seat.clone(), 
+                    fare: *fare, 
+                    booking_ref: ticket.as_ref()
+                        .and_then(|t| t.claims_unverified())
+                        .map(|c| c.booking_ref)
+                        .unwrap_or_default(),
+                    signed: ticket.clone(),
+                    secret: *secret,
+                });
+            }
+        }
+
+        Ok(status)
+    }
+
+    /// Takes a place in the waiting room of a flight
+    /// 
+    /// Repeated join returns the same place. 
+    /// Flight without a waiting room admits at once
+    pub async fn join_queue(&mut self, flight_num: u32) -> Result<QueuePlace>{
+        let packet = 
+            UdpPacket::new_with_request(PacketRequest::JoinQueue)
+                .set_data(&encode(&FlightRequest{ flight_num, passenger_id: self.id.clone() }));
+
+        let place = self.queue_request(packet).await?;
+        self.queue_tokens.insert(flight_num, place.token);
+
+        Ok(place)
+    }
+
+    /// Asks distributor about current place in the queue of a flight
+    /// 
+    /// Fails with `Rejection::NotAdmitted` if passenger did not join the queue
+    pub async fn queue_position(&mut self, flight_num: u32) -> Result<QueuePlace>{
+        let Some(&token) = self.queue_tokens.get(&flight_num) else {
+            return Err(Rejection::NotAdmitted{ position: None }.into());
+        };
+
+        let packet = 
+            UdpPacket::new_with_request(PacketRequest::QueuePosition)
+                .set_data(&encode(&QueueRequest{ flight_num, passenger_id: self.id.clone(), token }));
+
+        self.queue_request(packet).await
+    }
+
+    /// Joins the queue of a flight and polls the place every `poll` until admitted
+    pub async fn wait_for_admission(&mut self, flight_num: u32, poll: Duration) -> Result<()>{
+        let mut place = self.join_queue(flight_num).await?;
+
+        while !place.is_admitted() {
+            tokio::time::sleep(poll).await;
+            place = self.queue_position(flight_num).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn queue_request(&mut self, packet: UdpPacket) -> Result<QueuePlace>{
+        let packet = self.udp_client.send_and_recv(packet).await?;
+
+        match packet.response() {
+            PacketResponse::Ok => decode::<QueuePlace>(&packet)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Response is invalid")),
+            _ => Err(response_error(&packet)),
+        }
+    }
+}
+
+/// Converts unexpected response to error
+pub(crate) fn response_error(packet: &UdpPacket) -> Error{
+    if let Some(rejection) = Rejection::from_packet(packet) {
+        return rejection.into();
+    }
+
+    match packet.response(){
+        PacketResponse::Busy => Error::new(ErrorKind::WouldBlock, "Distributor is busy"),
+        _ => Error::new(ErrorKind::InvalidData, "Response is invalid"),
+    }
 }
 
 /// Used for saving acquired tickets on passenger side
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct FlightTicket{
     pub flight_num: u32,
     pub ticket: String, // [A-F][1-99]
+    pub fare: Fare,
+    pub booking_ref: String,
+    /// Ticket signed by the distributor, see `TicketVerifier`
+    pub signed: Option<SignedTicket>,
+    /// Secret proving ownership, needed to check in, change, transfer or cancel the ticket
+    pub secret: Option<u64>,
+}
+
+impl FlightTicket{
+    fn from_issued(flight_num: u32, issued: IssuedTicket) -> Self{
+        FlightTicket { 
+            flight_num, 
+            ticket: issued.seat, 
+            fare: issued.fare, 
+            booking_ref: issued.booking_ref, 
+            signed: issued.signed,
+            secret: issued.secret,
+        }
+    }
 }
\ No newline at end of file
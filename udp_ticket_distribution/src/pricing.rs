@@ -0,0 +1,152 @@
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use super::*;
+
+/// Market state of a flight a price is computed from
+#[derive(Debug, Clone, Copy)]
+pub struct PricingInput{
+    /// Fare set for the seat by `Distributor::set_fare`
+    pub base: Fare,
+    /// Seats still available, the seat being priced included
+    pub seats_left: u8,
+    /// Seats of the flight, sold and held ones included
+    pub seats_total: usize,
+    /// Time left until departure, `None` if departure is not set or passed
+    pub time_to_departure: Option<Duration>,
+    /// Tickets sold during the last hour
+    pub sales_last_hour: u32,
+}
+
+/// Computes current price of a seat
+/// 
+/// Implemented for closures `Fn(&PricingInput) -> u64`
+/// # Example
+/// ```rust
+/// # use udp_ticket_distribution::*;
+/// # use tokio::runtime::Runtime;
+/// # let mut rt = Runtime::new().unwrap();
+/// # rt.block_on(async {
+/// let distr = Distributor::new_with_address("127.0.0.1:0".to_string()).await.unwrap();
+/// 
+/// // Double price for the last ten seats
+/// distr.set_pricing(|input: &PricingInput| 
+///     if input.seats_left <= 10 { input.base.price * 2 } else { input.base.price });
+/// # });
+/// ```
+pub trait PricingPolicy: Send + Sync + 'static{
+    /// Returns price in minor currency units
+    fn price(&self, input: &PricingInput) -> u64;
+}
+
+impl<F> PricingPolicy for F
+where
+    F: Fn(&PricingInput) -> u64 + Send + Sync + 'static,
+{
+    fn price(&self, input: &PricingInput) -> u64{ self(input) }
+}
+
+/// Price grows with sold share of the flight, up to `max_markup` times the base at the last seat
+/// 
+/// Every sale during the last hour adds `velocity_markup` of the base on top
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InventoryPricing{
+    pub max_markup: f64,
+    pub velocity_markup: f64,
+}
+
+impl PricingPolicy for InventoryPricing{
+    fn price(&self, input: &PricingInput) -> u64{
+        let total = input.seats_total.max(1) as f64;
+        let taken = (total - input.seats_left as f64).max(0.0) / total;
+
+        let markup = 1.0 
+            + (self.max_markup - 1.0).max(0.0) * taken
+            + self.velocity_markup * input.sales_last_hour as f64;
+
+        (input.base.price as f64 * markup).round() as u64
+    }
+}
+
+/// Current pricing policy and hold settings
+pub(crate) struct Pricing{
+    policy: RwLock< Option<Arc<dyn PricingPolicy>> >,
+    hold_time: Mutex<Duration>,
+}
+
+impl Default for Pricing{
+    fn default() -> Self{
+        Pricing { policy: RwLock::new(None), hold_time: Mutex::new(Self::DEFAULT_HOLD_TIME) }
+    }
+}
+
+impl Pricing{
+    const DEFAULT_HOLD_TIME: Duration = Duration::from_secs(60);
+
+    pub fn set_policy(&self, policy: Option<Arc<dyn PricingPolicy>>){
+        *self.policy.write().unwrap() = policy;
+    }
+
+    pub fn hold_time(&self) -> Duration{ *self.hold_time.lock().unwrap() }
+
+    pub fn set_hold_time(&self, hold_time: Duration){ *self.hold_time.lock().unwrap() = hold_time; }
+
+    /// Returns fare of the seat with the price of the current policy, base fare without policy
+    pub fn fare(&self, flight: &FlightDB, seat: &str, now: SystemTime) -> Fare{
+        let base = flight.fare_of(seat);
+
+        let Some(policy) = self.policy.read().unwrap().clone() else { return base; };
+
+        let hour_ago = now.checked_sub(Duration::from_secs(3600)).unwrap_or(SystemTime::UNIX_EPOCH);
+
+        let input = PricingInput{
+            base,
+            seats_left: flight.info.seats_num,
+            seats_total: flight.seats.len() + flight.holds.len() + flight.sold.len(),
+            time_to_departure: flight.info.departure.and_then(|d| d.duration_since(now).ok()),
+            sales_last_hour: flight.sold.iter().filter(|t| t.sold_at >= hour_ago).count() as u32,
+        };
+
+        Fare{ price: policy.price(&input), ..base }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn inventory_pricing_grows_with_demand(){
+        let policy = InventoryPricing{ max_markup: 2.0, velocity_markup: 0.1 };
+        let input = PricingInput{
+            base: Fare{ class: FareClass::Economy, price: 1000 },
+            seats_left: 10,
+            seats_total: 10,
+            time_to_departure: None,
+            sales_last_hour: 0,
+        };
+
+        assert_eq!(policy.price(&input), 1000);
+        assert_eq!(policy.price(&PricingInput{ seats_left: 0, ..input }), 2000);
+        assert_eq!(policy.price(&PricingInput{ seats_left: 5, sales_last_hour: 2, ..input }), 1700);
+    }
+
+    #[test]
+    fn fare_counts_held_seats_and_recent_sales(){
+        let owner = TicketOwner{ passenger_id: None, ip: std::net::IpAddr::from([127, 0, 0, 1]) };
+        let mut flight = FlightDB::new(1, ["A1", "B1", "C1", "D1"].map(String::from).to_vec());
+        let fare = Fare{ class: FareClass::Economy, price: 1000 };
+        flight.set_fare(SeatRange::Rows(1..=1), fare);
+
+        let pricing = Pricing::default();
+        let now = SystemTime::now();
+        assert_eq!(pricing.fare(&flight, "A1", now), fare);
+
+        pricing.set_policy(Some(Arc::new(InventoryPricing{ max_markup: 2.0, velocity_markup: 0.5 })));
+        flight.sell_seat(owner.clone()).unwrap();
+        flight.hold_seat_at(0, owner, fare, now + Duration::from_secs(60)).unwrap();
+
+        // Half of the seats are taken and one was sold during the last hour
+        assert_eq!(pricing.fare(&flight, "B1", now).price, 2000);
+        assert_eq!(pricing.fare(&flight, "B1", now + Duration::from_secs(7200)).price, 1500);
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use super::*;
+
+/// Price reduction given by a promo code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Discount{
+    /// Percent of the price, 100 and more makes the ticket free
+    Percent(u8),
+    /// Amount in minor currency units, price never goes below zero
+    Fixed(u64),
+}
+
+impl Discount{
+    /// Returns discounted price
+    pub fn apply(&self, price: u64) -> u64{
+        match self{
+            // Computed in u128 so prices near u64::MAX don't overflow, result is never above `price`
+            Discount::Percent(percent) => (price as u128 * (100 - (*percent).min(100) as u128) / 100) as u64,
+            Discount::Fixed(amount) => price.saturating_sub(*amount),
+        }
+    }
+}
+
+/// Promo code definition
+/// 
+/// `None` fields are not restricted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromoCode{
+    pub discount: Discount,
+    /// Maximum number of tickets bought with the code
+    pub max_uses: Option<u32>,
+    /// Code is accepted from this time
+    pub valid_from: Option<SystemTime>,
+    /// Code is accepted until this time
+    pub valid_until: Option<SystemTime>,
+    /// Flights the code is accepted for
+    pub flights: Option<Vec<u32>>,
+}
+
+impl PromoCode{
+    /// Returns code with the discount and no restrictions
+    pub fn new(discount: Discount) -> Self{
+        PromoCode { discount, max_uses: None, valid_from: None, valid_until: None, flights: None }
+    }
+}
+
+/// Why a promo code was not accepted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PromoIssue{
+    /// Code is not defined
+    Unknown,
+    /// Code is not valid at this time
+    NotValidNow,
+    /// Code reached its `max_uses`
+    UsedUp,
+    /// Code is not accepted for the flight
+    WrongFlight,
+}
+
+struct PromoEntry{
+    promo: PromoCode,
+    uses: u32,
+}
+
+/// Defined promo codes and their usage
+#[derive(Default)]
+pub(crate) struct PromoBook{
+    codes: Mutex< HashMap<String, PromoEntry> >,
+}
+
+impl PromoBook{
+    /// Adds or replaces the code, usage count of a replaced code is kept
+    pub fn insert(&self, code: String, promo: PromoCode){
+        self.codes.lock().unwrap()
+            .entry(code)
+            .and_modify(|e| e.promo = promo.clone())
+            .or_insert(PromoEntry { promo, uses: 0 });
+    }
+
+    pub fn remove(&self, code: &str) -> Option<PromoCode>{
+        self.codes.lock().unwrap().remove(code).map(|e| e.promo)
+    }
+
+    pub fn uses(&self, code: &str) -> Option<u32>{
+        self.codes.lock().unwrap().get(code).map(|e| e.uses)
+    }
+
+    pub fn usage(&self) -> HashMap<String, u32>{
+        self.codes.lock().unwrap()
+            .iter()
+            .map(|(code, e)| (code.clone(), e.uses))
+            .collect()
+    }
+
+    /// Validates the code and counts one use of it
+    pub fn redeem(&self, code: &str, flight_num: u32, now: SystemTime) -> std::result::Result<Discount, Rejection>{
+        let mut codes = self.codes.lock().unwrap();
+
+        let entry = codes.get_mut(code).ok_or(Rejection::PromoInvalid{ issue: PromoIssue::Unknown })?;
+        let promo = &entry.promo;
+
+        let issue = if promo.valid_from.is_some_and(|from| now < from) || promo.valid_until.is_some_and(|until| now >= until) {
+            Some(PromoIssue::NotValidNow)
+        } else if promo.flights.as_ref().is_some_and(|f| !f.contains(&flight_num)) {
+            Some(PromoIssue::WrongFlight)
+        } else if promo.max_uses.is_some_and(|max| entry.uses >= max) {
+            Some(PromoIssue::UsedUp)
+        } else {
+            None
+        };
+
+        if let Some(issue) = issue {
+            return Err(Rejection::PromoInvalid{ issue });
+        }
+
+        let discount = promo.discount;
+        entry.uses+=1;
+
+        Ok(discount)
+    }
+
+    /// Returns use of the code back, used when ticket was not issued
+    pub fn refund(&self, code: &str){
+        if let Some(entry) = self.codes.lock().unwrap().get_mut(code) {
+            entry.uses = entry.uses.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn discount_apply(){
+        assert_eq!(Discount::Percent(25).apply(1000), 750);
+        assert_eq!(Discount::Percent(150).apply(1000), 0);
+        assert_eq!(Discount::Fixed(300).apply(1000), 700);
+        assert_eq!(Discount::Fixed(3000).apply(1000), 0);
+        assert_eq!(Discount::Percent(50).apply(u64::MAX), u64::MAX / 2);
+        assert_eq!(Discount::Percent(0).apply(u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn promo_redeem(){
+        let book = PromoBook::default();
+        let now = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(100);
+        let invalid = |issue| Err(Rejection::PromoInvalid{ issue });
+
+        book.insert("HALF".to_string(), PromoCode{ max_uses: Some(1), flights: Some(vec![1]), ..PromoCode::new(Discount::Percent(50)) });
+        book.insert("LATER".to_string(), PromoCode{ valid_from: Some(now + std::time::Duration::from_secs(60)), ..PromoCode::new(Discount::Fixed(100)) });
+
+        assert_eq!(book.redeem("HALF", 2, now), invalid(PromoIssue::WrongFlight));
+        assert_eq!(book.redeem("HALF", 1, now), Ok(Discount::Percent(50)));
+        assert_eq!(book.redeem("HALF", 1, now), invalid(PromoIssue::UsedUp));
+        assert_eq!(book.redeem("LATER", 1, now), invalid(PromoIssue::NotValidNow));
+        assert_eq!(book.redeem("NONE", 1, now), invalid(PromoIssue::Unknown));
+
+        // Refunded use is available again
+        book.refund("HALF");
+        assert_eq!(book.redeem("HALF", 1, now), Ok(Discount::Percent(50)));
+
+        // Replaced code keeps its usage
+        book.insert("HALF".to_string(), PromoCode::new(Discount::Fixed(100)));
+        assert_eq!(book.uses("HALF"), Some(1));
+        assert_eq!(book.usage().get("LATER"), Some(&0));
+    }
+}
@@ -0,0 +1,131 @@
+use std::time::Instant;
+use rand_core::{OsRng, RngCore};
+use super::*;
+
+/// Place of a passenger in the waiting room
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuePlace{
+    /// Token proving the place, sent with `RequestTicket`
+    pub token: u64,
+    /// Position in the queue, `0` when admitted to purchase
+    pub position: u32,
+}
+
+impl QueuePlace{
+    /// Returns `true` if the passenger may request a ticket
+    pub fn is_admitted(&self) -> bool{ self.position == 0 }
+}
+
+/// Waiting room in front of a flight's sale
+/// 
+/// Passengers are admitted in order of joining:
+/// `burst` of them at once, then `per_second`.
+/// An admission is good for one purchase, the next one needs joining the queue again
+#[derive(Debug, Clone)]
+pub struct WaitingRoom{
+    rate: RateLimit,
+    admitted: f64,
+    updated: Instant,
+    queue: Vec<QueueEntry>,
+}
+
+#[derive(Debug, Clone)]
+struct QueueEntry{
+    token: u64,
+    owner: TicketOwner,
+    used: bool,
+}
+
+impl WaitingRoom{
+    /// Returns empty waiting room admitting passengers at `rate`
+    pub fn new(rate: RateLimit, now: Instant) -> Self{
+        WaitingRoom { 
+            rate, 
+            admitted: rate.burst as f64, 
+            updated: now, 
+            queue: Vec::new(), 
+        }
+    }
+
+    /// Returns admission rate
+    pub fn rate(&self) -> RateLimit{ self.rate }
+
+    /// Returns number of passengers who joined the queue
+    pub fn len(&self) -> usize{ self.queue.len() }
+
+    /// Returns `true` if nobody joined the queue
+    pub fn is_empty(&self) -> bool{ self.queue.is_empty() }
+
+    /// Changes admission rate, passengers admitted before stay admitted
+    pub fn set_rate(&mut self, rate: RateLimit, now: Instant){
+        self.advance(now);
+        self.rate = rate;
+    }
+
+    /// Puts the owner to the end of the queue, 
+    /// repeated joins of the same passenger or address return the same place until it is used
+    pub(crate) fn join(&mut self, owner: TicketOwner, now: Instant) -> QueuePlace{
+        self.advance(now);
+
+        let index = match self.queue.iter().position(|e| !e.used && e.owner.same_as(&owner)){
+            Some(index) => index,
+            None => {
+                self.queue.push(QueueEntry{ token: OsRng.next_u64(), owner, used: false });
+                self.queue.len() - 1
+            },
+        };
+
+        self.place_at(index)
+    }
+
+    /// Returns place of the token, `None` if it was not given to the owner or is used
+    pub(crate) fn place(&mut self, token: u64, owner: &TicketOwner, now: Instant) -> Option<QueuePlace>{
+        self.advance(now);
+
+        let index = self.position(token, owner)?;
+
+        Some(self.place_at(index))
+    }
+
+    /// Uses up the admission of the token after a purchase
+    pub(crate) fn use_admission(&mut self, token: Option<u64>, owner: &TicketOwner){
+        if let Some(index) = token.and_then(|t| self.position(t, owner)) {
+            self.queue[index].used = true;
+        }
+    }
+
+    /// Checks if the token lets the owner buy a ticket
+    pub(crate) fn check(&mut self, token: Option<u64>, owner: &TicketOwner, now: Instant) -> std::result::Result<(), Rejection>{
+        let place = token.and_then(|t| self.place(t, owner, now));
+
+        match place{
+            Some(place) if place.is_admitted() => Ok(()),
+            place => Err(Rejection::NotAdmitted{ position: place.map(|p| p.position) }),
+        }
+    }
+
+    /// Admits passengers for the time passed since the last update
+    /// 
+    /// Unused admissions are kept up to `burst` over the queue length,
+    /// so after a quiet period only `burst` newcomers pass at once
+    fn advance(&mut self, now: Instant){
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+
+        self.admitted = (self.admitted + elapsed*self.rate.per_second)
+            .min((self.queue.len() + self.rate.burst as usize) as f64);
+        self.updated = now;
+    }
+
+    fn position(&self, token: u64, owner: &TicketOwner) -> Option<usize>{
+        self.queue.iter().position(|e| !e.used && e.token == token && e.owner.same_as(owner))
+    }
+
+    fn place_at(&self, index: usize) -> QueuePlace{
+        let admitted = self.admitted as usize;
+
+        QueuePlace{
+            token: self.queue[index].token,
+            position: (index + 1).saturating_sub(admitted) as u32,
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use super::*;
+
+/// Maximum number of tickets a single passenger or address may acquire
+/// 
+/// `None` means no limit. Passenger ID and source IP are counted separately,
+/// both have to be under the limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaRules{
+    /// Limit for tickets of one flight
+    pub per_flight: Option<u32>,
+    /// Limit for tickets of all flights
+    pub overall: Option<u32>,
+}
+
+/// Current quota rules and count of tickets acquired by every identity
+#[derive(Default)]
+pub(crate) struct QuotaBook{
+    rules: Mutex<QuotaRules>,
+    acquired: Mutex< HashMap<String, u32> >,
+}
+
+impl QuotaBook{
+    pub fn rules(&self) -> QuotaRules{ *self.rules.lock().unwrap() }
+
+    pub fn set_rules(&self, rules: QuotaRules){ *self.rules.lock().unwrap() = rules; }
+
+    /// Counts one more ticket for every key if overall quota allows it
+    /// 
+    /// Tickets are counted even without overall quota,
+    /// so quota set later takes earlier purchases into account
+    pub fn reserve(&self, keys: &[String]) -> std::result::Result<(), Rejection>{
+        self.check(keys, 0)?;
+        self.add(keys);
+
+        Ok(())
+    }
+
+    /// Checks if overall quota allows one more ticket besides `pending` not counted yet
+    pub fn check(&self, keys: &[String], pending: u32) -> std::result::Result<(), Rejection>{
+        let Some(limit) = self.rules().overall else { return Ok(()); };
+        let acquired = self.acquired.lock().unwrap();
+
+        if keys.iter().any(|k| acquired.get(k).copied().unwrap_or(0).saturating_add(pending) >= limit) {
+            return Err(Rejection::QuotaExceeded{ scope: QuotaScope::Overall, limit });
+        }
+
+        Ok(())
+    }
+
+    /// Counts one more ticket for every key regardless of quota
+    pub fn add(&self, keys: &[String]){
+        let mut acquired = self.acquired.lock().unwrap();
+
+        for key in keys{
+            *acquired.entry(key.clone()).or_insert(0) += 1;
+        }
+    }
+
+    /// Returns reserved ticket back, used when ticket was not issued
+    pub fn release(&self, keys: &[String]){
+        let mut acquired = self.acquired.lock().unwrap();
+
+        for key in keys{
+            if let Some(count) = acquired.get_mut(key) {
+                *count = count.saturating_sub(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn overall_quota_counts_every_key(){
+        let book = QuotaBook::default();
+        let keys = |id: &str| vec!["ip:127.0.0.1".to_string(), format!("id:{}", id)];
+
+        // Tickets are counted before any quota is set
+        book.reserve(&keys("a")).unwrap();
+        book.set_rules(QuotaRules{ per_flight: None, overall: Some(2) });
+        book.reserve(&keys("a")).unwrap();
+
+        let exceeded = Err(Rejection::QuotaExceeded{ scope: QuotaScope::Overall, limit: 2 });
+        assert_eq!(book.reserve(&keys("b")), exceeded);
+
+        book.release(&keys("a"));
+        assert_eq!(book.check(&keys("b"), 1), exceeded);
+        assert_eq!(book.reserve(&keys("b")), Ok(()));
+
+        book.release(&["id:unknown".to_string()]);
+        assert_eq!(book.reserve(&keys("a")), exceeded);
+    }
+}
@@ -0,0 +1,59 @@
+/// SplitMix64 generator
+/// 
+/// Output depends only on the seed and never changes between builds,
+/// so draws made with it can be reproduced for audits
+#[derive(Debug, Clone)]
+pub(crate) struct SeededRng{
+    state: u64,
+}
+
+impl SeededRng{
+    pub fn new(seed: u64) -> Self{ SeededRng { state: seed } }
+
+    /// Returns seed made from current time
+    pub fn time_seed() -> u64{
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64)
+    }
+
+    pub fn next_u64(&mut self) -> u64{
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns number in range `0..bound`
+    pub fn below(&mut self, bound: usize) -> usize{
+        (self.next_u64() % bound.max(1) as u64) as usize
+    }
+
+    /// Fisher-Yates shuffle
+    pub fn shuffle<T>(&mut self, items: &mut [T]){
+        for i in (1..items.len()).rev(){
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn seeded_rng_is_reproducible(){
+        let mut first: Vec<u32> = (0..50).collect();
+        let mut second = first.clone();
+
+        SeededRng::new(42).shuffle(&mut first);
+        SeededRng::new(42).shuffle(&mut second);
+
+        assert_eq!(first, second);
+        assert_ne!(first, (0..50).collect::<Vec<u32>>());
+        assert_eq!(SeededRng::new(0).next_u64(), 0xE220_A839_7B1D_CDAF);
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use super::*;
+
+/// Decides which shard serves a flight
+///
+/// Both `Distributor`s and `PassengerRouter` of one setup
+/// must use the same map, otherwise flights will not be found
+#[derive(Debug, Clone, Default)]
+pub enum ShardMap{
+    /// Flight is served by shard `hash(num) % shards_count`
+    #[default]
+    Hash,
+    /// Flight is served by the mapped shard,
+    /// unmapped flights fall back to `Hash`
+    Explicit(HashMap<u32, usize>),
+}
+
+impl ShardMap{
+    /// Returns index of the shard which serves specified flight
+    /// # Example
+    /// ```rust
+    /// # use udp_ticket_distribution::ShardMap;
+    /// let map = ShardMap::Explicit([(7, 2)].into());
+    ///
+    /// assert_eq!(map.shard_for(7, 3), 2);
+    /// assert_eq!(map.shard_for(8, 3), ShardMap::Hash.shard_for(8, 3));
+    /// ```
+    pub fn shard_for(&self, flight_num: u32, shards_count: usize) -> usize{
+        if let ShardMap::Explicit(map) = self {
+            if let Some(&shard) = map.get(&flight_num) {
+                return shard;
+            }
+        }
+
+        // Fibonacci hashing, stable between builds and processes unlike `DefaultHasher`
+        let hash = (flight_num as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32;
+
+        (hash % shards_count.max(1) as u64) as usize
+    }
+}
+
+/// Describes distributor's place in a sharded setup
+#[derive(Debug, Clone)]
+pub struct ShardConfig{
+    /// Index of this shard, `0..count`
+    pub index: usize,
+    /// Total number of shards
+    pub count: usize,
+    /// Routing rule shared by the whole setup
+    pub map: ShardMap,
+}
+
+impl ShardConfig{
+    /// Returns error if `index` is out of `0..count` or the map routes to an unknown shard
+    pub fn validate(&self) -> Result<()>{
+        if self.index >= self.count {
+            return Err(Error::new(ErrorKind::InvalidInput, "Shard index is out of shards count"));
+        }
+
+        if let ShardMap::Explicit(map) = &self.map {
+            if map.values().any(|&shard| shard >= self.count) {
+                return Err(Error::new(ErrorKind::InvalidInput, "Shard map routes to unknown shard"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if specified flight is routed to this shard
+    pub fn owns(&self, flight_num: u32) -> bool{
+        self.map.shard_for(flight_num, self.count) == self.index
+    }
+}
+
+/// Passenger side router over several sharded `Distributor`s
+///
+/// Ticket requests are sent to the shard which serves the flight,
+/// flight listings are fetched from every shard and merged.
+/// Other requests about a flight go to its shard, see `PassengerRouter::shard_for`
+pub struct PassengerRouter{
+    shards: Vec<Passenger>,
+    map: ShardMap,
+}
+
+impl PassengerRouter{
+    /// Returns `Result<PassengerRouter>` with one connected `Passenger` per shard
+    ///
+    /// Order of addresses is the order of shard indices
+    pub async fn connect(shards_addr: &[String], map: ShardMap) -> Result<Self>{
+        let mut shards = Vec::with_capacity(shards_addr.len());
+
+        for addr in shards_addr{
+            let mut psngr = Passenger::new().await?;
+            psngr.try_connect(addr).await?;
+            shards.push(psngr);
+        }
+
+        Ok(PassengerRouter { shards, map })
+    }
+
+    /// Returns number of shards
+    pub fn shards_count(&self) -> usize{ self.shards.len() }
+
+    /// Sets passenger ID on every shard, see `Passenger::set_id`
+    pub fn set_id(&mut self, id: impl Into<String>){
+        let id = id.into();
+
+        for psngr in self.shards.iter_mut(){
+            psngr.set_id(id.clone());
+        }
+    }
+
+    /// Returns `Passenger` connected to the shard which serves the flight
+    /// 
+    /// Use it for holds, check-in, transfers and other requests about the flight
+    pub fn shard_for(&mut self, flight_num: u32) -> Result<&mut Passenger>{
+        let shard = self.map.shard_for(flight_num, self.shards.len());
+
+        self.shards.get_mut(shard).ok_or_else(||
+            Error::new(ErrorKind::NotFound, "Flight is routed to unknown shard"))
+    }
+
+    /// Returns all previosly acquired tickets from every shard
+    pub fn acquired_tickets(&self) -> Vec<FlightTicket>{
+        self.shards
+            .iter()
+            .flat_map(|p| p.acquired_tickets())
+            .collect()
+    }
+
+    /// Fetch available flights from every shard
+    ///
+    /// Returned flights are sorted by flight number
+    pub async fn fetch_flights(&mut self) -> Result<Vec<FlightInfo>>{
+        let listings = futures::future::join_all(
+            self.shards.iter_mut().map(|p| p.fetch_flights())
+        ).await;
+
+        let mut flights = Vec::new();
+        for listing in listings{
+            flights.extend(listing?);
+        }
+
+        flights.sort_by_key(|f| f.num);
+
+        Ok(flights)
+    }
+
+    /// Query a ticket from the shard which serves the flight
+    ///
+    /// Returns `None` if there is no tickets are available for this flight
+    pub async fn query_ticket_for_a_flight(&mut self, flight_num: u32) -> Result<Option<String>>{
+        self.shard_for(flight_num)?.query_ticket_for_a_flight(flight_num).await
+    }
+}
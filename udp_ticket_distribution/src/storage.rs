@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
+use super::*;
+
+/// Storage of flights with a separate lock for every flight
+///
+/// The list lock is taken for writing only when a flight is added,
+/// so sales of unrelated flights never wait for each other.
+/// Locks are never held across `.await`
+#[derive(Default)]
+pub struct FlightStorage{
+    flights: RwLock<BTreeMap<u32, Arc<Mutex<FlightDB>>>>,
+}
+
+impl FlightStorage{
+    /// Returns empty storage
+    pub fn new() -> Self{ Self::default() }
+
+    /// Returns number of stored flights
+    pub fn len(&self) -> usize{ self.flights.read().unwrap().len() }
+
+    /// Returns `true` if there are no flights
+    pub fn is_empty(&self) -> bool{ self.len() == 0 }
+
+    /// Returns shared handle of a flight
+    pub fn flight(&self, flight_num: u32) -> Option<Arc<Mutex<FlightDB>>>{
+        self.flights.read().unwrap().get(&flight_num).cloned()
+    }
+
+    /// Adds new flight
+    ///
+    /// `make_flight` gets the biggest flight number in storage
+    /// and is called under the list lock, so numbers are never reused
+    pub fn push_with(&self, make_flight: impl FnOnce(Option<u32>) -> FlightDB) -> u32{
+        self.try_push_with(|max_num| Some(make_flight(max_num))).unwrap()
+    }
+
+    /// Adds new flight unless `make_flight` returns `None`
+    ///
+    /// Same as `FlightStorage::push_with` otherwise
+    pub fn try_push_with(&self, make_flight: impl FnOnce(Option<u32>) -> Option<FlightDB>) -> Option<u32>{
+        let mut lock = self.flights.write().unwrap();
+
+        let max_num = lock.keys().next_back().copied();
+        let flight = make_flight(max_num)?;
+        let num = flight.info.num;
+
+        lock.insert(num, Mutex::new(flight).into());
+
+        Some(num)
+    }
+
+    /// Returns info of every flight sorted by flight number
+    ///
+    /// Every `FlightInfo` is read under its flight lock
+    /// after holds expired by `now` are returned to sale
+    pub fn flights_info(&self, now: SystemTime) -> Vec<FlightInfo>{
+        self.handles()
+            .iter()
+            .map(|f| {
+                let mut flight = f.lock().unwrap();
+                flight.release_expired(now);
+                flight.info
+            })
+            .collect()
+    }
+
+    /// Returns copy of every flight sorted by flight number
+    pub fn snapshot(&self) -> Vec<FlightDB>{
+        self.handles()
+            .iter()
+            .map(|f| f.lock().unwrap().clone())
+            .collect()
+    }
+
+    /// Takes a seat from the flight
+    ///
+    /// Only this flight is locked during the operation
+    pub fn take_seat(&self, flight_num: u32) -> Option<String>{
+        self.flight(flight_num)?
+            .lock().unwrap()
+            .take_seat()
+    }
+
+    pub(crate) fn handles(&self) -> Vec<Arc<Mutex<FlightDB>>>{
+        self.flights.read().unwrap().values().cloned().collect()
+    }
+}
@@ -0,0 +1,204 @@
+use std::io::{Error, ErrorKind};
+use std::sync::RwLock;
+use std::time::SystemTime;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand_core::{OsRng, RngCore};
+use super::*;
+use super::msg::*;
+
+/// Ticket data covered by the distributor's signature
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TicketClaims{
+    pub flight_num: u32,
+    /// Seat code, e.g. "A1"
+    pub seat: String,
+    /// Passenger ID the ticket was sold to, `None` for passengers without ID
+    pub passenger_id: Option<String>,
+    /// Booking reference of the sale
+    pub booking_ref: String,
+    /// Time of the sale
+    pub issued_at: SystemTime,
+    /// Number of changes of the ticket, a re-signed ticket replaces the ones with lower revision
+    #[serde(default)]
+    pub revision: u32,
+}
+
+/// Ticket signed with the distributor's Ed25519 key
+/// 
+/// Check it with `TicketVerifier`, only the public key of the distributor is needed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedTicket{
+    claims: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl SignedTicket{
+    /// Returns claims of the ticket **without** checking the signature
+    pub fn claims_unverified(&self) -> Option<TicketClaims>{
+        serde_cbor::from_slice(&self.claims).ok()
+    }
+
+    /// Returns ticket serialized for storing or printing as a code
+    pub fn to_bytes(&self) -> Vec<u8>{ encode(self) }
+
+    /// Returns ticket from bytes made by `SignedTicket::to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self>{
+        serde_cbor::from_slice(bytes)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Ticket is malformed"))
+    }
+}
+
+/// Checks tickets offline with the distributor's public key
+/// # Example
+/// ```rust
+/// # use udp_ticket_distribution::*;
+/// # use tokio::runtime::Runtime;
+/// # let mut rt = Runtime::new().unwrap();
+/// # rt.block_on(async {
+/// let distr = Distributor::new_with_address("127.0.0.1:0".to_string()).await.unwrap();
+/// distr.gen_fake_flight(1).await;
+/// 
+/// // Public key is given to gate agents once
+/// let verifier = TicketVerifier::new(&distr.public_key()).unwrap();
+/// 
+/// let mut psngr = Passenger::new().await.unwrap();
+/// psngr.try_connect(&distr.get_address()).await.unwrap();
+/// psngr.query_ticket_for_a_flight(1).await.unwrap();
+/// 
+/// let ticket = psngr.acquired_tickets()[0].signed.clone().unwrap();
+/// let claims = verifier.verify(&ticket).unwrap();
+/// assert_eq!(claims.flight_num, 1);
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct TicketVerifier{
+    key: VerifyingKey,
+}
+
+impl TicketVerifier{
+    /// Returns verifier for the public key, see `Distributor::public_key`
+    pub fn new(public_key: &[u8; 32]) -> Result<Self>{
+        let key = VerifyingKey::from_bytes(public_key)
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "Public key is invalid"))?;
+
+        Ok(TicketVerifier { key })
+    }
+
+    /// Returns claims of the ticket if it was signed by the distributor
+    pub fn verify(&self, ticket: &SignedTicket) -> Result<TicketClaims>{
+        let invalid = || Error::new(ErrorKind::InvalidData, "Ticket signature is invalid");
+
+        let signature = Signature::from_slice(&ticket.signature).map_err(|_| invalid())?;
+        self.key.verify_strict(&ticket.claims, &signature).map_err(|_| invalid())?;
+
+        ticket.claims_unverified().ok_or_else(invalid)
+    }
+
+    /// Returns claims of the ticket serialized with `SignedTicket::to_bytes`
+    pub fn verify_bytes(&self, bytes: &[u8]) -> Result<TicketClaims>{
+        self.verify(&SignedTicket::from_bytes(bytes)?)
+    }
+}
+
+/// Distributor's signing key and the keys it replaced
+pub(crate) struct TicketSigner{
+    key: RwLock<SigningKey>,
+    previous: RwLock<Vec<VerifyingKey>>,
+}
+
+impl Default for TicketSigner{
+    fn default() -> Self{
+        TicketSigner { key: RwLock::new(SigningKey::generate(&mut OsRng)), previous: RwLock::new(Vec::new()) }
+    }
+}
+
+impl TicketSigner{
+    /// Replaces the signing key, public key of the replaced one stays accepted by `TicketSigner::verify`
+    pub fn set_key(&self, secret_key: &[u8; 32]){
+        let key = SigningKey::from_bytes(secret_key);
+        let replaced = std::mem::replace(&mut *self.key.write().unwrap(), key.clone()).verifying_key();
+
+        let mut previous = self.previous.write().unwrap();
+        previous.retain(|k| *k != key.verifying_key() && *k != replaced);
+        if replaced != key.verifying_key() {
+            previous.push(replaced);
+        }
+    }
+
+    pub fn public_key(&self) -> [u8; 32]{
+        self.key.read().unwrap().verifying_key().to_bytes()
+    }
+
+    /// Returns current public key followed by the replaced ones, latest first
+    pub fn public_keys(&self) -> Vec<[u8; 32]>{
+        let previous = self.previous.read().unwrap();
+
+        std::iter::once(self.public_key())
+            .chain(previous.iter().rev().map(|k| k.to_bytes()))
+            .collect()
+    }
+
+    /// Returns claims of the ticket signed with the current or a replaced key
+    pub fn verify(&self, ticket: &SignedTicket) -> Option<TicketClaims>{
+        self.public_keys()
+            .iter()
+            .find_map(|key| TicketVerifier::new(key).ok()?.verify(ticket).ok())
+    }
+
+    pub fn sign(&self, flight_num: u32, ticket: &SoldTicket) -> SignedTicket{
+        let claims = encode(&TicketClaims{
+            flight_num,
+            seat: ticket.seat.clone(),
+            passenger_id: ticket.owner.passenger_id.clone(),
+            booking_ref: ticket.booking_ref.clone(),
+            issued_at: ticket.sold_at,
+            revision: ticket.revision,
+        });
+
+        let signature = self.key.read().unwrap().sign(&claims).to_bytes().to_vec();
+
+        SignedTicket { claims, signature }
+    }
+}
+
+/// Returns random secret proving ownership of a ticket
+pub(crate) fn new_ticket_secret() -> u64{ OsRng.next_u64() }
+
+/// Returns random booking reference of 6 letters and digits
+pub(crate) fn new_booking_ref() -> String{
+    // Without 0, O, 1 and I, which are easy to confuse
+    const ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+    let mut bits = OsRng.next_u64();
+
+    (0..6).map(|_| {
+        let c = ALPHABET[(bits % ALPHABET.len() as u64) as usize] as char;
+        bits /= ALPHABET.len() as u64;
+        c
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::net::IpAddr;
+
+    #[test]
+    fn replaced_keys_stay_verifiable(){
+        let signer = TicketSigner::default();
+        signer.set_key(&[1; 32]);
+
+        let mut flight = FlightDB::new(1, vec!["A1".to_string()]);
+        let sold = flight.sell_seat(TicketOwner{ passenger_id: None, ip: IpAddr::from([127, 0, 0, 1]) }).unwrap();
+        let old = signer.sign(1, &sold);
+
+        signer.set_key(&[2; 32]);
+        signer.set_key(&[2; 32]);
+        assert_eq!(signer.public_keys().len(), 3);
+        assert_eq!(signer.verify(&old).map(|c| c.booking_ref), Some(sold.booking_ref.clone()));
+        assert!(signer.verify(&signer.sign(1, &sold)).is_some());
+
+        let stranger = TicketSigner::default();
+        assert_eq!(signer.verify(&stranger.sign(1, &sold)), None);
+    }
+}
@@ -7,6 +7,8 @@ pub struct ClientSide{
     socket: UdpSocket,
     is_connected: bool,
     buff: Vec<u8>,
+    busy_retries: u32,
+    busy_delay: Duration,
 }
 
 impl ClientSide {
@@ -30,7 +32,13 @@ impl ClientSide {
             AddressSelection::Manual(addr) => UdpSocket::bind(addr).await?,
         };
 
-        let client = ClientSide { socket, is_connected: false, buff: vec![0u8; 1024] };
+        let client = ClientSide { 
+            socket, 
+            is_connected: false, 
+            buff: vec![0u8; MAX_DATAGRAM],
+            busy_retries: 0,
+            busy_delay: Duration::ZERO,
+        };
 
         Ok(client)
     }
@@ -59,6 +67,16 @@ impl ClientSide {
     /// ```
     pub fn connection_status(&self) -> bool{ self.is_connected }
 
+    /// Makes `send_and_recv` resend the packet when server answers with `PacketResponse::Busy`
+    /// 
+    /// Before the n-th retry client waits for `delay * n`.
+    /// After `retries` attempts `Busy` packet is returned as is.
+    /// By default client does not retry
+    pub fn set_busy_retry(&mut self, retries: u32, delay: Duration){
+        self.busy_retries = retries;
+        self.busy_delay = delay;
+    }
+
     /// Returns client's `SocketAddr`
     pub fn local_addr(&self) -> SocketAddr{
         self.socket.local_addr().unwrap()
@@ -147,13 +165,21 @@ impl ClientSide {
         }
         
         let packet = packet.to_bytes();
+        let mut attempt = 0;
+
+        loop {
+            self.socket.send(&packet).await?;
 
-        self.socket.send(&packet).await?;
+            let n = self.socket.recv( &mut self.buff).await?;
 
-        let n = self.socket.recv( &mut self.buff).await?;
+            let response : UdpPacket = self.buff[..n].to_vec().into();
 
-        let packet : UdpPacket = self.buff[..n].to_vec().into();
+            if response.response() != PacketResponse::Busy || attempt >= self.busy_retries {
+                return Ok(response);
+            }
 
-        Ok(packet)
+            attempt+=1;
+            tokio::time::sleep(self.busy_delay * attempt).await;
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,44 @@
+use std::time::{Duration, Instant, SystemTime};
+use super::*;
+
+/// Holds info about recieved packet which is not a part of `UdpPacket`
+/// 
+/// Passed to async processing fn of `ServerSide` 
+/// to allow per-client logging, rate limiting and session tracking
+#[derive(Debug, Clone, Copy)]
+pub struct RequestContext{
+    peer_addr: SocketAddr,
+    received_at: SystemTime,
+    received_instant: Instant,
+}
+
+impl RequestContext{
+    /// Returns context of a packet recieved right now from specified address
+    /// # Example
+    /// ```rust
+    /// # use udp_tools::*;
+    /// let addr = "127.0.0.1:8080".parse().unwrap();
+    /// let ctx = RequestContext::new(addr);
+    /// 
+    /// assert_eq!(ctx.peer_addr(), addr);
+    /// ```
+    pub fn new(peer_addr: SocketAddr) -> Self{
+        RequestContext { 
+            peer_addr, 
+            received_at: SystemTime::now(), 
+            received_instant: Instant::now(),
+        }
+    }
+
+    /// Returns sender's `SocketAddr`
+    pub fn peer_addr(&self) -> SocketAddr{ self.peer_addr }
+
+    /// Returns wall clock time when the packet was recieved
+    pub fn received_at(&self) -> SystemTime{ self.received_at }
+
+    /// Returns monotonic time when the packet was recieved
+    pub fn received_instant(&self) -> Instant{ self.received_instant }
+
+    /// Returns time passed since the packet was recieved
+    pub fn elapsed(&self) -> Duration{ self.received_instant.elapsed() }
+}
@@ -0,0 +1,149 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use super::*;
+
+/// Boxed future returned by processing fns and middlewares
+pub type PacketFuture = Pin< Box< dyn Future<Output = UdpPacket> + Send > >;
+
+pub(crate) type FnTraitAsync = Arc< dyn (Fn(UdpPacket, RequestContext) -> PacketFuture) + Send + Sync >;
+
+/// Cross-cutting layer around server's processing fn
+///
+/// Layer may change the packet before passing it to `Next`,
+/// change the response after, or answer by itself without calling `Next` at all.
+///
+/// Implemented for closures `Fn(UdpPacket, RequestContext, Next) -> impl Future<Output = UdpPacket>`
+/// # Example
+/// ```rust
+/// # use udp_tools::*;
+/// # use udp_tools::AddressSelection::*;
+/// # use tokio::runtime::Runtime;
+/// # let mut rt = Runtime::new().unwrap();
+/// # rt.block_on(async {
+/// # let serv_addr = Manual("127.0.0.1:8097".to_string());
+/// # let mut server =
+/// #     ServerSide::new_with_address(serv_addr.clone()).await.unwrap();
+/// // Logging layer
+/// server.add_layer(|p: UdpPacket, ctx: RequestContext, next: Next| async move {
+///     let request = p.request();
+///     let p = next.run(p, ctx).await;
+///     println!("{} {:?} -> {:?} in {:?}", ctx.peer_addr(), request, p.response(), ctx.elapsed());
+///     p
+/// });
+/// server.start();
+///
+/// let mut client =
+///     ClientSide::new_with_address(Auto).await.unwrap();
+///
+/// client.set_server(serv_addr).await.unwrap();
+/// assert_eq!(client.connection_status(), true);
+/// # });
+/// ```
+pub trait Middleware: Send + Sync + 'static{
+    /// Processes the packet, `next` holds the rest of the chain
+    fn call(&self, packet: UdpPacket, ctx: RequestContext, next: Next) -> PacketFuture;
+}
+
+impl<F, Fut> Middleware for F
+where
+    F: (Fn(UdpPacket, RequestContext, Next) -> Fut) + Send + Sync + 'static,
+    Fut: Future<Output = UdpPacket> + Send + 'static,
+{
+    fn call(&self, packet: UdpPacket, ctx: RequestContext, next: Next) -> PacketFuture{
+        Box::pin(self(packet, ctx, next))
+    }
+}
+
+/// Rest of the processing chain after a `Middleware`
+#[derive(Clone)]
+pub struct Next{
+    handler: FnTraitAsync,
+}
+
+impl Next{
+    /// Passes the packet to the next layer or to the processing fn
+    pub fn run(self, packet: UdpPacket, ctx: RequestContext) -> PacketFuture{
+        (self.handler)(packet, ctx)
+    }
+}
+
+/// Wraps the handler into layers, first layer is the outermost one
+pub(crate) fn compose(handler: FnTraitAsync, layers: &[Arc<dyn Middleware>]) -> FnTraitAsync{
+    layers.iter().rev().fold(handler, |handler, layer| {
+        let layer = layer.clone();
+
+        Arc::new(move |packet, ctx|
+            layer.call(packet, ctx, Next { handler: handler.clone() }))
+    })
+}
+
+/// Middleware counting processed packets and time spent on them
+///
+/// Clones share the same counters, so one clone can be added
+/// to the server and another one kept for reading
+/// # Example
+/// ```rust
+/// # use udp_tools::*;
+/// # use udp_tools::AddressSelection::*;
+/// # use tokio::runtime::Runtime;
+/// # let mut rt = Runtime::new().unwrap();
+/// # rt.block_on(async {
+/// # let serv_addr = Manual("127.0.0.1:8098".to_string());
+/// # let mut server =
+/// #     ServerSide::new_with_address(serv_addr.clone()).await.unwrap();
+/// let metrics = MetricsLayer::new();
+/// server.add_layer(metrics.clone());
+/// server.start();
+///
+/// let mut client =
+///     ClientSide::new_with_address(Auto).await.unwrap();
+/// client.set_server(serv_addr).await.unwrap();
+///
+/// assert_eq!(metrics.processed(), 1);
+/// # });
+/// ```
+#[derive(Clone, Default)]
+pub struct MetricsLayer{
+    processed: Arc<AtomicU64>,
+    unanswered: Arc<AtomicU64>,
+    total_micros: Arc<AtomicU64>,
+}
+
+impl MetricsLayer{
+    /// Returns layer with zeroed counters
+    pub fn new() -> Self{ Self::default() }
+
+    /// Returns number of processed packets
+    pub fn processed(&self) -> u64{ self.processed.load(Ordering::Relaxed) }
+
+    /// Returns number of packets left without response (`PacketResponse::None`)
+    pub fn unanswered(&self) -> u64{ self.unanswered.load(Ordering::Relaxed) }
+
+    /// Returns average time from recieving a packet to its response
+    pub fn average_time(&self) -> Duration{
+        let processed = self.processed().max(1);
+
+        Duration::from_micros(self.total_micros.load(Ordering::Relaxed) / processed)
+    }
+}
+
+impl Middleware for MetricsLayer{
+    fn call(&self, packet: UdpPacket, ctx: RequestContext, next: Next) -> PacketFuture{
+        let metrics = self.clone();
+
+        Box::pin(async move {
+            let packet = next.run(packet, ctx).await;
+
+            if packet.response() == PacketResponse::None{
+                metrics.unanswered.fetch_add(1, Ordering::Relaxed);
+            }
+            metrics.total_micros.fetch_add(ctx.elapsed().as_micros() as u64, Ordering::Relaxed);
+            metrics.processed.fetch_add(1, Ordering::Relaxed);
+
+            packet
+        })
+    }
+}
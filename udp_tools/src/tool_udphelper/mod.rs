@@ -1,14 +1,21 @@
 pub use self::clientside::ClientSide;
-pub use self::serverside::ServerSide;
+pub use self::serverside::{ServerSide, ShutdownSummary, ServerStatus, ConcurrencyLimit, OverflowPolicy};
+pub use self::context::RequestContext;
+pub use self::middleware::{Middleware, Next, PacketFuture, MetricsLayer};
 
 mod clientside;
 mod serverside;
+mod context;
+mod middleware;
 
 use tokio::net::UdpSocket;
 use std::io::{Result, Error, ErrorKind};
 use super::tool_udppacket::*;
 use std::net::SocketAddr;
 
+/// Biggest payload of a UDP datagram over IPv4, receive buffers have this size
+const MAX_DATAGRAM: usize = 65507;
+
 /// Holds two input variants of address
 /// 
 /// Manual variant requires "ip:port" string
@@ -109,4 +116,304 @@ mod tests{
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn client_and_server_async_processing() -> Result<()>{
+        let mut server = ServerSide::new_with_address(Manual("127.0.0.1:0".to_string())).await?;
+
+        server.set_async_processing_fn(| packet, ctx | async move {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+            assert!(ctx.elapsed() >= std::time::Duration::from_millis(10));
+
+            match packet.request(){
+                PacketRequest::Ping => packet.set_response(PacketResponse::Ok),
+                _ => packet
+                    .set_response(PacketResponse::Ok)
+                    .set_data(ctx.peer_addr().to_string().as_bytes()),
+            }
+        });
+        let server_addr = server.local_addr().to_string();
+
+        server.start();
+
+        let mut client = ClientSide::new_with_address(Manual("127.0.0.1:0".to_string())).await?;
+        client.set_server(Manual(server_addr)).await?;
+
+        let packet = client.send_and_recv(
+                UdpPacket::new_with_request(PacketRequest::GetFlights)
+        ).await?;
+
+        server.stop();
+
+        assert_eq!(packet.response(), PacketResponse::Ok);
+        assert_eq!(packet.try_retrieve_data().unwrap(), client.local_addr().to_string().into_bytes());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn client_and_server_layers_order() -> Result<()>{
+        let mut server = ServerSide::new_with_address(Manual("127.0.0.1:0".to_string())).await?;
+
+        server.set_processing_fn(| packet | {
+            let mut data = packet.try_retrieve_data().unwrap_or_default();
+            data.push(b'f');
+
+            packet
+                .set_response(PacketResponse::Ok)
+                .set_data(&data)
+        });
+
+        for tag in [b'1', b'2']{
+            server.add_layer(move | packet: UdpPacket, ctx, next: Next | async move {
+                let mut data = packet.try_retrieve_data().unwrap_or_default();
+                data.push(tag);
+
+                let packet = next.run(packet.set_data(&data), ctx).await;
+
+                let mut data = packet.try_retrieve_data().unwrap();
+                data.push(tag);
+                packet.set_data(&data)
+            });
+        }
+
+        // Validation layer answers without calling the processing fn
+        server.add_layer(| packet: UdpPacket, ctx, next: Next | async move {
+            match packet.request(){
+                PacketRequest::RequestTicket => packet.set_response(PacketResponse::ErrorInRequest),
+                _ => next.run(packet, ctx).await,
+            }
+        });
+
+        let metrics = MetricsLayer::new();
+        server.add_layer(metrics.clone());
+
+        let server_addr = server.local_addr().to_string();
+
+        server.start();
+
+        let mut client = ClientSide::new_with_address(Manual("127.0.0.1:0".to_string())).await?;
+        client.set_server(Manual(server_addr)).await?;
+
+        let packet = client.send_and_recv(
+                UdpPacket::new_with_request(PacketRequest::GetFlights)
+        ).await?;
+        assert_eq!(packet.try_retrieve_data().unwrap(), b"12f21".to_vec());
+
+        let packet = client.send_and_recv(
+                UdpPacket::new_with_request(PacketRequest::RequestTicket)
+        ).await?;
+        assert_eq!(packet.response(), PacketResponse::ErrorInRequest);
+        assert_eq!(packet.try_retrieve_data().unwrap(), b"1221".to_vec());
+
+        server.stop();
+
+        // Ping and GetFlights passed validation, RequestTicket did not
+        assert_eq!(metrics.processed(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn server_graceful_shutdown() -> Result<()>{
+        use std::time::Duration;
+
+        let mut server = ServerSide::new_with_address(Manual("127.0.0.1:0".to_string())).await?;
+
+        server.set_async_processing_fn(| packet, _ | async move {
+            let millis = match packet.request(){
+                PacketRequest::Ping => 0,
+                PacketRequest::GetFlights => 100,
+                _ => 10_000,
+            };
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+
+            packet.set_response(PacketResponse::Ok)
+        });
+        let server_addr = server.local_addr().to_string();
+
+        server.start();
+
+        let mut client = ClientSide::new_with_address(Manual("127.0.0.1:0".to_string())).await?;
+        client.set_server(Manual(server_addr.clone())).await?;
+
+        let raw = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+        raw.send_to(&UdpPacket::new_with_request(PacketRequest::GetFlights).to_bytes(), &server_addr).await?;
+        raw.send_to(&UdpPacket::new_with_request(PacketRequest::RequestTicket).to_bytes(), &server_addr).await?;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let summary = server.shutdown(Duration::from_millis(500)).await?;
+
+        // Ping finished before shutdown and is not counted, GetFlights during drain, RequestTicket is too long
+        assert_eq!(summary.completed, 1);
+        assert_eq!(summary.failed, 0);
+        assert_eq!(summary.aborted, 1);
+        assert!(!summary.is_drained());
+        assert!(!server.is_running());
+
+        let mut buff = vec![0u8; 1024];
+        let n = raw.recv(&mut buff).await?;
+        let packet: UdpPacket = buff[..n].to_vec().into();
+        assert_eq!(packet.request(), PacketRequest::GetFlights);
+
+        let err = client.send_and_recv(UdpPacket::new_with_request(PacketRequest::Ping));
+        assert!(tokio::time::timeout(Duration::from_millis(200), err).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn server_stop_finishes_in_flight_packets() -> Result<()>{
+        use std::time::Duration;
+
+        let mut server = ServerSide::new_with_address(Manual("127.0.0.1:0".to_string())).await?;
+
+        server.set_async_processing_fn(| packet, _ | async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            packet.set_response(PacketResponse::Ok)
+        });
+        let server_addr = server.local_addr().to_string();
+
+        server.start();
+
+        let raw = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+        raw.send_to(&UdpPacket::new_with_request(PacketRequest::RequestTicket).to_bytes(), &server_addr).await?;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        server.stop();
+        assert!(!server.is_running());
+
+        let mut buff = vec![0u8; 1024];
+        let n = tokio::time::timeout(Duration::from_secs(1), raw.recv(&mut buff)).await??;
+        let packet: UdpPacket = buff[..n].to_vec().into();
+        assert_eq!(packet.response(), PacketResponse::Ok);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn server_hot_swap_processing_fn() -> Result<()>{
+        let mut server = ServerSide::new_with_address(Manual("127.0.0.1:0".to_string())).await?;
+        let server_addr = server.local_addr().to_string();
+
+        server.start();
+
+        let mut client = ClientSide::new_with_address(Manual("127.0.0.1:0".to_string())).await?;
+        client.set_server(Manual(server_addr)).await?;
+
+        server.set_processing_fn(| packet | packet.set_response(PacketResponse::TicketsSold));
+
+        let packet = client.send_and_recv(
+                UdpPacket::new_with_request(PacketRequest::RequestTicket)
+        ).await?;
+        assert_eq!(packet.response(), PacketResponse::TicketsSold);
+
+        server.add_layer(| packet: UdpPacket, _, _ | async move {
+            packet.set_response(PacketResponse::ErrorInRequest)
+        });
+
+        let packet = client.send_and_recv(
+                UdpPacket::new_with_request(PacketRequest::RequestTicket)
+        ).await?;
+        assert_eq!(packet.response(), PacketResponse::ErrorInRequest);
+        assert!(server.is_running());
+
+        server.stop();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn server_status_and_error_reporting() -> Result<()>{
+        use std::sync::{Arc, Mutex};
+
+        let mut server = ServerSide::new_with_address(Manual("127.0.0.1:0".to_string())).await?;
+        let server_addr = server.local_addr().to_string();
+
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let errors_clone = errors.clone();
+        server.set_error_fn(move |e| errors_clone.lock().unwrap().push(e.kind()));
+
+        let mut status = server.status_receiver();
+        assert_eq!(server.status(), ServerStatus::Stopped);
+        assert!(!server.is_running());
+
+        server.start();
+        status.changed().await.unwrap();
+        assert_eq!(*status.borrow(), ServerStatus::Running);
+        assert!(server.is_running());
+
+        // Not a packet, decoding error is reported and server keeps running
+        let raw = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+        raw.send_to(b"garbage", &server_addr).await?;
+
+        let mut client = ClientSide::new_with_address(Manual("127.0.0.1:0".to_string())).await?;
+        client.set_server(Manual(server_addr)).await?;
+
+        for _ in 0..100 {
+            if !errors.lock().unwrap().is_empty() { break; }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(*errors.lock().unwrap(), vec![ErrorKind::InvalidData]);
+        assert!(server.is_running());
+
+        server.stop();
+        status.changed().await.unwrap();
+        assert_eq!(*status.borrow(), ServerStatus::Stopped);
+        assert!(!server.is_running());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn server_concurrency_limit_overflow() -> Result<()>{
+        use std::time::Duration;
+
+        let mut server = ServerSide::new_with_address(Manual("127.0.0.1:0".to_string())).await?;
+
+        server.set_async_processing_fn(| packet, _ | async move {
+            if packet.request() == PacketRequest::GetFlights {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+            }
+            packet.set_response(PacketResponse::Ok)
+        });
+        server.set_concurrency_limit(Some(ConcurrencyLimit{
+            max_in_flight: 1,
+            queue_capacity: 1,
+            overflow: OverflowPolicy::ReplyBusy,
+        }));
+        let server_addr = server.local_addr().to_string();
+
+        server.start();
+
+        let mut client = ClientSide::new_with_address(Manual("127.0.0.1:0".to_string())).await?;
+        client.set_server(Manual(server_addr.clone())).await?;
+
+        // First one is processed, second one is queued
+        let raw = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+        for _ in 0..2{
+            raw.send_to(&UdpPacket::new_with_request(PacketRequest::GetFlights).to_bytes(), &server_addr).await?;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let packet = client.send_and_recv(
+                UdpPacket::new_with_request(PacketRequest::RequestTicket)
+        ).await?;
+        assert_eq!(packet.response(), PacketResponse::Busy);
+        assert_eq!(packet.request(), PacketRequest::RequestTicket);
+
+        // Retries wait until the queue is free
+        client.set_busy_retry(10, Duration::from_millis(50));
+        let packet = client.send_and_recv(
+                UdpPacket::new_with_request(PacketRequest::RequestTicket)
+        ).await?;
+        assert_eq!(packet.response(), PacketResponse::Ok);
+
+        let summary = server.shutdown(Duration::from_secs(1)).await?;
+        assert!(summary.overflowed >= 2);
+        assert!(summary.is_drained());
+
+        Ok(())
+    }
+}
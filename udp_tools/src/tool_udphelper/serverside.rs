@@ -1,19 +1,149 @@
-use std::sync::Arc;
-use tokio::task::JoinHandle;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, watch};
+use tokio::task::{JoinHandle, JoinSet};
 use super::*;
-
-type FnTraitAsync = Arc< dyn (Fn(UdpPacket) -> UdpPacket) + Send + Sync >;
+use super::middleware::{FnTraitAsync, compose};
 
 fn fn_trait_into_async(func: impl (Fn(UdpPacket) -> UdpPacket) + Send + Sync + 'static) -> FnTraitAsync{
-    Arc::new(func) 
+    Arc::new(move |packet, _| Box::pin(std::future::ready(func(packet))))
+}
+
+fn async_fn_into_async<F, Fut>(func: F) -> FnTraitAsync
+where
+    F: (Fn(UdpPacket, RequestContext) -> Fut) + Send + Sync + 'static,
+    Fut: Future<Output = UdpPacket> + Send + 'static,
+{
+    Arc::new(move |packet, ctx| Box::pin(func(packet, ctx)))
+}
+
+/// Time `ServerSide::stop` lets in-flight packets finish in background
+const STOP_DRAIN: Duration = Duration::from_secs(5);
+
+/// Result of `ServerSide::shutdown`
+/// 
+/// Counts only packets which were in processing or in queue when shutdown started
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownSummary{
+    /// Packets processed and answered after the shutdown signal
+    pub completed: usize,
+    /// Packets whose processing failed after the shutdown signal (send error or panic in processing fn)
+    pub failed: usize,
+    /// Packets still in processing or in queue when the deadline passed, their tasks are aborted
+    pub aborted: usize,
+    /// Packets dropped or answered with `Busy` because of `ConcurrencyLimit`
+    pub overflowed: usize,
+    /// Time spent waiting for in-flight packets
+    pub drain_time: Duration,
+}
+
+impl ShutdownSummary{
+    /// Returns `true` if every in-flight packet was processed before the deadline
+    pub fn is_drained(&self) -> bool{ self.aborted == 0 }
+
+    fn count(&mut self, task_result: std::result::Result<Result<()>, tokio::task::JoinError>) -> Option<Error>{
+        let error = task_error(task_result);
+
+        match error{
+            Some(_) => self.failed+=1,
+            None => self.completed+=1,
+        }
+
+        error
+    }
+}
+
+/// Returns error of a finished processing task
+fn task_error(task_result: std::result::Result<Result<()>, tokio::task::JoinError>) -> Option<Error>{
+    match task_result{
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(e),
+        Err(e) => Some(Error::other(e)),
+    }
+}
+
+/// State of server's receive loop
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerStatus{
+    /// Server was not started or was stopped
+    Stopped,
+    /// Server recieves packets
+    Running,
+    /// Receive loop stopped because of an error
+    Failed{
+        kind: ErrorKind,
+        message: String,
+    },
+}
+
+/// What to do with a packet recieved when both 
+/// concurrency limit and queue are full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy{
+    /// Recieved packet is dropped
+    DropNewest,
+    /// The oldest queued packet is dropped, recieved one is queued
+    DropOldest,
+    /// Recieved packet is answered with `PacketResponse::Busy`
+    ReplyBusy,
+}
+
+/// Limits number of packets processed at the same time
+#[derive(Debug, Clone, Copy)]
+pub struct ConcurrencyLimit{
+    /// Maximum number of packets in processing, at least one
+    pub max_in_flight: usize,
+    /// Maximum number of packets waiting for processing
+    pub queue_capacity: usize,
+    /// Policy for packets which do not fit into the queue
+    pub overflow: OverflowPolicy,
+}
+
+type ErrorFn = Arc< dyn Fn(&Error) + Send + Sync >;
+
+/// Everything receive loop needs besides the shutdown signal
+struct LoopState{
+    socket: Arc<UdpSocket>,
+    handler: Arc<HandlerCell>,
+    status_tx: Arc< watch::Sender<ServerStatus> >,
+    error_fn: Option<ErrorFn>,
+    auto_restart: Option<Duration>,
+    limit: Option<ConcurrencyLimit>,
+}
+
+impl LoopState{
+    fn report(&self, error: &Error){
+        if let Some(error_fn) = &self.error_fn { error_fn(error); }
+    }
+}
+
+/// Holds currently used handler (processing fn wrapped into layers)
+/// 
+/// Receive loop loads the handler for every packet, 
+/// so a stored handler is used starting from the next packet
+struct HandlerCell(RwLock<FnTraitAsync>);
+
+impl HandlerCell{
+    fn load(&self) -> FnTraitAsync{ self.0.read().unwrap().clone() }
+
+    fn store(&self, handler: FnTraitAsync){ *self.0.write().unwrap() = handler; }
 }
 
 /// Provides server implementation over UdpSocket
 pub struct ServerSide{
     socket: Arc<UdpSocket>,
     is_running: bool,
-    loop_handle: Option< JoinHandle< Result<()> > >,
+    loop_handle: Option< JoinHandle< Result<ShutdownSummary> > >,
+    shutdown_tx: Option< oneshot::Sender<Duration> >,
     processing_fn: FnTraitAsync,
+    layers: Vec< Arc<dyn Middleware> >,
+    handler: Arc<HandlerCell>,
+    status_tx: Arc< watch::Sender<ServerStatus> >,
+    error_fn: Option<ErrorFn>,
+    auto_restart: Option<Duration>,
+    limit: Option<ConcurrencyLimit>,
 }
 
 impl ServerSide {
@@ -37,11 +167,20 @@ impl ServerSide {
             AddressSelection::Manual(addr) => UdpSocket::bind(addr).await?,
         };
 
+        let processing_fn = fn_trait_into_async( | p | p.set_response(PacketResponse::Ok) );
+
         let server = ServerSide { 
             socket: socket.into(),
             is_running: false,
             loop_handle: None,
-            processing_fn:  fn_trait_into_async( | p | p.set_response(PacketResponse::Ok) )
+            shutdown_tx: None,
+            handler: Arc::new(HandlerCell(RwLock::new(processing_fn.clone()))),
+            processing_fn,
+            layers: Vec::new(),
+            status_tx: Arc::new(watch::channel(ServerStatus::Stopped).0),
+            error_fn: None,
+            auto_restart: None,
+            limit: None,
         };
 
         Ok(server)
@@ -49,7 +188,8 @@ impl ServerSide {
 
     /// Sets server's logic over recieved `UdpPacket`s
     /// 
-    /// Requires server restart to update fn logic
+    /// Can be called on a running server: packets recieved after the call
+    /// are processed by the new fn, packets already in processing finish with the old one
     /// 
     /// If packet response is `None` then packet will not be sent
     /// # Example
@@ -77,10 +217,135 @@ impl ServerSide {
     /// ```
     pub fn set_processing_fn(&mut self, processing_fn: impl (Fn(UdpPacket) -> UdpPacket) + Send + Sync + 'static){
         self.processing_fn = fn_trait_into_async(processing_fn);
+        self.update_handler();
+    }
+
+    /// Sets server's async logic over recieved `UdpPacket`s
+    /// 
+    /// Same as `set_processing_fn`, but the handler also gets `RequestContext`
+    /// with sender's address and recieve time, and may `.await` inside, 
+    /// e.g. on a database or another service
+    /// # Example
+    /// ```rust
+    /// # use udp_tools::*;
+    /// # use udp_tools::AddressSelection::*;
+    /// # use tokio::runtime::Runtime;
+    /// # let mut rt = Runtime::new().unwrap();
+    /// # rt.block_on(async {
+    /// # let serv_addr = Manual("127.0.0.1:8096".to_string());
+    /// # let mut server = 
+    /// #     ServerSide::new_with_address(serv_addr.clone()).await.unwrap();
+    /// server.set_async_processing_fn(|p, _ctx| async move {
+    ///     tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    ///     p.set_response(PacketResponse::Ok)
+    /// });
+    /// server.start();
+    /// 
+    /// let mut client = 
+    ///     ClientSide::new_with_address(Auto).await.unwrap(); 
+    /// 
+    /// client.set_server(serv_addr).await.unwrap();
+    /// assert_eq!(client.connection_status(), true);
+    /// # });
+    /// ```
+    pub fn set_async_processing_fn<F, Fut>(&mut self, processing_fn: F)
+    where
+        F: (Fn(UdpPacket, RequestContext) -> Fut) + Send + Sync + 'static,
+        Fut: Future<Output = UdpPacket> + Send + 'static,
+    {
+        self.processing_fn = async_fn_into_async(processing_fn);
+        self.update_handler();
+    }
+
+    /// Adds a `Middleware` around server's processing fn
+    /// 
+    /// Layers are called in the order they were added, 
+    /// the first added layer is the outermost one
+    /// 
+    /// Applied on a running server without restart
+    pub fn add_layer(&mut self, layer: impl Middleware){
+        self.layers.push(Arc::new(layer));
+        self.update_handler();
+    }
+
+    /// Removes all added layers
+    /// 
+    /// Applied on a running server without restart
+    pub fn clear_layers(&mut self){
+        self.layers.clear();
+        self.update_handler();
     }
 
-    /// Returns server running status
-    pub fn is_running(&self) -> bool{ self.is_running }
+    fn update_handler(&self){
+        self.handler.store(compose(self.processing_fn.clone(), &self.layers));
+    }
+
+    /// Sets callback for errors of the receive loop and of packets processing
+    /// 
+    /// Callback is called from server's tasks, requires server restart to update
+    /// # Example
+    /// ```rust
+    /// # use udp_tools::*;
+    /// # use udp_tools::AddressSelection::*;
+    /// # use tokio::runtime::Runtime;
+    /// # let mut rt = Runtime::new().unwrap();
+    /// # rt.block_on(async {
+    /// # let mut server = 
+    /// #     ServerSide::new_with_address(Manual("127.0.0.1:0".to_string())).await.unwrap();
+    /// server.set_error_fn(|e| eprintln!("Server error: {}", e));
+    /// server.start();
+    /// # });
+    /// ```
+    pub fn set_error_fn(&mut self, error_fn: impl Fn(&Error) + Send + Sync + 'static){
+        self.error_fn = Some(Arc::new(error_fn));
+    }
+
+    /// Makes receive loop continue after `recv_from` errors instead of stopping
+    /// 
+    /// Loop waits for `backoff` before the next attempt, `None` disables restarts.
+    /// Requires server restart to update
+    pub fn set_auto_restart(&mut self, backoff: Option<Duration>){
+        self.auto_restart = backoff;
+    }
+
+    /// Limits number of packets processed at the same time, `None` removes the limit
+    /// 
+    /// Without limit every recieved packet is processed in a new task immediately.
+    /// Requires server restart to update
+    /// # Example
+    /// ```rust
+    /// # use udp_tools::*;
+    /// # use udp_tools::AddressSelection::*;
+    /// # use tokio::runtime::Runtime;
+    /// # let mut rt = Runtime::new().unwrap();
+    /// # rt.block_on(async {
+    /// # let mut server = 
+    /// #     ServerSide::new_with_address(Manual("127.0.0.1:0".to_string())).await.unwrap();
+    /// server.set_concurrency_limit(Some(ConcurrencyLimit{
+    ///     max_in_flight: 64,
+    ///     queue_capacity: 256,
+    ///     overflow: OverflowPolicy::ReplyBusy,
+    /// }));
+    /// server.start();
+    /// # });
+    /// ```
+    pub fn set_concurrency_limit(&mut self, limit: Option<ConcurrencyLimit>){
+        self.limit = limit;
+    }
+
+    /// Returns `true` only if receive loop is actually running
+    /// 
+    /// Becomes `false` after `stop`, `shutdown` or a failure of the loop
+    pub fn is_running(&self) -> bool{ 
+        self.is_running && self.loop_handle.as_ref().is_some_and(|h| !h.is_finished())
+    }
+
+    /// Returns current state of the receive loop
+    pub fn status(&self) -> ServerStatus{ self.status_tx.borrow().clone() }
+
+    /// Returns channel which recieves every change of server's status
+    pub fn status_receiver(&self) -> watch::Receiver<ServerStatus>{ self.status_tx.subscribe() }
+
     /// Returns server's `SocketAddr`
     pub fn local_addr(&self) -> SocketAddr{
         self.socket.local_addr().unwrap()
@@ -119,13 +384,24 @@ impl ServerSide {
     pub fn start(&mut self){
         self.stop();
 
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        self.shutdown_tx = Some(shutdown_tx);
+
         self.loop_handle = Some(
             tokio::spawn(
                 Self::loop_recv(
-                    self.socket.clone(), 
-                    self.processing_fn.clone()))
+                    LoopState{
+                        socket: self.socket.clone(), 
+                        handler: self.handler.clone(),
+                        status_tx: self.status_tx.clone(),
+                        error_fn: self.error_fn.clone(),
+                        auto_restart: self.auto_restart,
+                        limit: self.limit,
+                    },
+                    shutdown_rx))
         );
 
+        self.status_tx.send_replace(ServerStatus::Running);
         self.is_running = true;
     }
 
@@ -158,32 +434,200 @@ impl ServerSide {
     /// assert_eq!(client.connection_status(), true);
     /// # });
     /// ```
+    /// 
+    /// Packets which are still in processing finish in background for up to 5 seconds,
+    /// use `shutdown` to wait for them and get a summary
     pub fn stop(&mut self){
-        if let Some(handle) = &self.loop_handle {
-            if !handle.is_finished() { handle.abort()}
+        // Loop drains its tasks and exits on its own, aborting it would abort the tasks
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            let _ = shutdown_tx.send(STOP_DRAIN);
         }
 
+        self.loop_handle = None;
         self.is_running = false;
+        self.status_tx.send_replace(ServerStatus::Stopped);
     }
 
-    async fn process_recieved_packet(socket: Arc<UdpSocket>, data:Vec<u8>, addr: SocketAddr, processing_fn: FnTraitAsync ) -> Result<()>{
-        let packet: UdpPacket = data.into();
-        let packet = processing_fn(packet);
+    /// Server will stop recieving incoming packets 
+    /// and wait for in-flight packets up to `deadline`
+    /// 
+    /// Packets not processed before the deadline are dropped without response
+    /// # Example
+    /// ```rust
+    /// # use udp_tools::*;
+    /// # use udp_tools::AddressSelection::*;
+    /// # use tokio::runtime::Runtime;
+    /// # use std::time::Duration;
+    /// # let mut rt = Runtime::new().unwrap();
+    /// # rt.block_on(async {
+    /// # let serv_addr = Manual("127.0.0.1:8099".to_string());
+    /// # let mut server = 
+    /// #     ServerSide::new_with_address(serv_addr.clone()).await.unwrap();
+    /// server.start();
+    /// 
+    /// let mut client = 
+    ///     ClientSide::new_with_address(Auto).await.unwrap(); 
+    /// client.set_server(serv_addr).await.unwrap();
+    /// 
+    /// let summary = server.shutdown(Duration::from_secs(1)).await.unwrap();
+    /// 
+    /// assert!(summary.is_drained());
+    /// assert_eq!(server.is_running(), false);
+    /// # });
+    /// ```
+    pub async fn shutdown(&mut self, deadline: Duration) -> Result<ShutdownSummary>{
+        self.is_running = false;
+
+        let (Some(shutdown_tx), Some(handle)) = (self.shutdown_tx.take(), self.loop_handle.take()) else {
+            return Ok(ShutdownSummary::default());
+        };
+
+        // Loop could already be finished with an error, it is returned below
+        let _ = shutdown_tx.send(deadline);
+
+        let summary = handle.await.unwrap_or_else(|e| Err(Error::other(e)))?;
+
+        self.status_tx.send_replace(ServerStatus::Stopped);
+
+        Ok(summary)
+    }
+
+    async fn process_recieved_packet(socket: Arc<UdpSocket>, data:Vec<u8>, ctx: RequestContext, processing_fn: FnTraitAsync ) -> Result<()>{
+        let packet = UdpPacket::try_from_bytes(&data)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("Malformed packet from {}: {}", ctx.peer_addr(), e)))?;
+        let packet = processing_fn(packet, ctx).await;
 
         if packet.response() != PacketResponse::None{
-            socket.send_to(&packet.to_bytes(), addr).await?;
+            socket.send_to(&packet.to_bytes(), ctx.peer_addr()).await?;
         }
         Ok(())
     }
 
-    async fn loop_recv(socket: Arc<UdpSocket>, processing_fn: FnTraitAsync ) -> Result<()>{
-        let mut buff = vec![0u8; 1024];
-        loop {
-            let (n, addr) = socket.recv_from(&mut buff).await?;
+    async fn loop_recv(state: LoopState, mut shutdown_rx: oneshot::Receiver<Duration> ) -> Result<ShutdownSummary>{
+        let mut buff = vec![0u8; MAX_DATAGRAM];
+        let mut dispatcher = Dispatcher::default();
+        let mut summary = ShutdownSummary::default();
+
+        let deadline = loop {
+            tokio::select! {
+                biased;
+                deadline = &mut shutdown_rx => break deadline.unwrap_or_default(),
+                Some(res) = dispatcher.tasks.join_next(), if !dispatcher.tasks.is_empty() => {
+                    if let Some(e) = task_error(res) { state.report(&e); }
+                    dispatcher.spawn_queued(&state);
+                },
+                res = state.socket.recv_from(&mut buff) => {
+                    let (n, addr) = match res {
+                        Ok(recieved) => recieved,
+                        Err(e) => {
+                            state.report(&e);
+
+                            let Some(backoff) = state.auto_restart else {
+                                state.status_tx.send_replace(ServerStatus::Failed{ 
+                                    kind: e.kind(), 
+                                    message: e.to_string(),
+                                });
+                                return Err(e);
+                            };
+
+                            tokio::time::sleep(backoff).await;
+                            continue;
+                        },
+                    };
+                    let ctx = RequestContext::new(addr);
+
+                    if let Some(rejected) = dispatcher.accept(&state, buff[..n].to_vec(), ctx) {
+                        summary.overflowed+=1;
+
+                        if let Some(busy) = rejected {
+                            if let Err(e) = state.socket.send_to(&busy.to_bytes(), addr).await {
+                                state.report(&e);
+                            }
+                        }
+                    }
+                }
+            }
+        };
+
+        let drain_start = Instant::now();
+
+        let _ = tokio::time::timeout(deadline, async {
+            while let Some(res) = dispatcher.tasks.join_next().await {
+                if let Some(e) = summary.count(res) { state.report(&e); }
+                dispatcher.spawn_queued(&state);
+            }
+        }).await;
+
+        summary.drain_time = drain_start.elapsed();
+        summary.aborted = dispatcher.tasks.len() + dispatcher.queue.len();
+        dispatcher.tasks.abort_all();
+
+        Ok(summary)
+    }
+}
+
+/// Spawns processing tasks within `ConcurrencyLimit`
+#[derive(Default)]
+struct Dispatcher{
+    tasks: JoinSet< Result<()> >,
+    queue: VecDeque<(Vec<u8>, RequestContext)>,
+}
+
+impl Dispatcher{
+    /// Spawns, queues or rejects recieved packet
+    /// 
+    /// Returns `Some` if a packet was rejected, with `Busy` response if it has to be sent
+    fn accept(&mut self, state: &LoopState, data: Vec<u8>, ctx: RequestContext) -> Option< Option<UdpPacket> >{
+        let Some(limit) = state.limit else {
+            self.spawn(state, data, ctx);
+            return None;
+        };
+
+        if self.tasks.len() < limit.max_in_flight.max(1) {
+            self.spawn(state, data, ctx);
+            return None;
+        }
+
+        if self.queue.len() < limit.queue_capacity {
+            self.queue.push_back((data, ctx));
+            return None;
+        }
+
+        match limit.overflow{
+            OverflowPolicy::DropNewest => Some(None),
+            OverflowPolicy::DropOldest => {
+                self.queue.pop_front();
+                self.queue.push_back((data, ctx));
+                Some(None)
+            },
+            OverflowPolicy::ReplyBusy => {
+                let busy = UdpPacket::try_from_bytes(&data)
+                    .ok()
+                    .map(|p| UdpPacket::new_with_request(p.request()).set_response(PacketResponse::Busy));
+                Some(busy)
+            },
+        }
+    }
+
+    /// Spawns queued packets while there is a free place
+    fn spawn_queued(&mut self, state: &LoopState){
+        let max_in_flight = state.limit.map_or(usize::MAX, |l| l.max_in_flight.max(1));
 
-            tokio::task::spawn(
-                ServerSide::process_recieved_packet(socket.clone(), buff[..n].to_vec(), addr, processing_fn.clone())
-            );
+        while self.tasks.len() < max_in_flight {
+            let Some((data, ctx)) = self.queue.pop_front() else { break; };
+            self.spawn(state, data, ctx);
         }
     }
-}
\ No newline at end of file
+
+    fn spawn(&mut self, state: &LoopState, data: Vec<u8>, ctx: RequestContext){
+        self.tasks.spawn(
+            ServerSide::process_recieved_packet(state.socket.clone(), data, ctx, state.handler.load())
+        );
+    }
+}
+
+impl Drop for ServerSide {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
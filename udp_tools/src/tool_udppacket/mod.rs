@@ -104,7 +104,7 @@ impl UdpPacket{
     /// 
     /// assert_eq!(packet.request(), PacketRequest::Ping);
     /// ```
-    pub fn request(&self) -> PacketRequest { self.request.clone() }
+    pub fn request(&self) -> PacketRequest { self.request }
 
     /// Converts packet to bytes vector
     /// # Example
@@ -146,6 +146,23 @@ impl UdpPacket{
     }
 }   
 
+impl UdpPacket{
+    /// Returns `Result<UdpPacket>` parsed from bytes
+    /// 
+    /// Unlike `From<Vec<u8>>` does not panic on invalid bytes
+    /// # Example
+    /// ```rust
+    /// # use udp_tools::*;
+    /// let bytes = UdpPacket::new_with_request(PacketRequest::Ping).to_bytes();
+    /// 
+    /// assert_eq!(UdpPacket::try_from_bytes(&bytes).unwrap().request(), PacketRequest::Ping);
+    /// assert!(UdpPacket::try_from_bytes(b"garbage").is_err());
+    /// ```
+    pub fn try_from_bytes(data: &[u8]) -> Result<UdpPacket,String>{
+        serde_cbor::from_reader::<UdpPacket, _>(data).map_err(|e| e.to_string())
+    }
+}
+
 impl From<Vec<u8>> for UdpPacket {
     /// Creates packet from bytes vector
     /// # Example
@@ -173,12 +190,25 @@ impl From<Vec<u8>> for UdpPacket {
 }
 
 /// Holds possible client request
-#[derive(Debug, PartialEq, Clone, Serialize_repr, Deserialize_repr)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize_repr, Deserialize_repr)]
 #[repr(u8)]
 pub enum PacketRequest{
     Ping=1,
     GetFlights,
     RequestTicket,
+    EnterLottery,
+    LotteryResult,
+    JoinQueue,
+    QueuePosition,
+    HoldTicket,
+    BuyHeld,
+    CheckIn,
+    GateScan,
+    TransferTicket,
+    MyTickets,
+    ChangeSeat,
+    CancelTicket,
+    BookItinerary,
 }
 
 /// Holds possible server response
@@ -189,6 +219,22 @@ pub enum PacketResponse{
     Ok,
     ErrorInRequest,
     TicketsSold,
+    Busy,
+    RateLimited,
+    QuotaExceeded,
+    LotteryOnly,
+    LotteryClosed,
+    NotAdmitted,
+    SaleNotOpen,
+    SaleClosed,
+    HoldExpired,
+    PromoInvalid,
+    TicketNotFound,
+    CheckInNotOpen,
+    CheckInClosed,
+    AlreadyBoarded,
+    SeatUnavailable,
+    GateNotAllowed,
 }
 
 #[cfg(test)]
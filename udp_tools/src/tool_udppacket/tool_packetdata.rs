@@ -6,7 +6,7 @@ pub struct PacketData{
     data: Vec<u8>,
 }
 
-fn make_crc( data: &Vec<u8>) -> u16 {
+fn make_crc( data: &[u8]) -> u16 {
     use crc::{Crc,CRC_16_IBM_SDLC};
     let crc = Crc::<u16>::new(&CRC_16_IBM_SDLC);
     crc.checksum(data)
@@ -15,11 +15,10 @@ fn make_crc( data: &Vec<u8>) -> u16 {
 impl PacketData {
     pub fn new_with_data(data: &[u8])-> PacketData{
         let data = data.to_vec();//copy
-        let packet = PacketData {
+        PacketData {
             crc: make_crc(&data),
             data,
-        };
-        packet
+        }
     }
 
     pub fn try_retrieve_data(&self) -> Result<&Vec<u8>, String> {